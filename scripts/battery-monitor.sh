@@ -8,14 +8,22 @@ BATTERY_PATH=$(find /sys/class/power_supply/ -name "BAT*" | head -1)
 AC_PATH=$(find /sys/class/power_supply/ -name "AC*" | head -1)
 [ -z "$BATTERY_PATH" ] && echo "No battery found" && exit 1
 
+# Keep a pino instance up, so --print-id returns at once and the low battery ID
+# stays valid for --close. This exits right away if one is already running.
+pino --daemon 2>/dev/null &
+sleep 0.5
+
 last_level=$(cat "$BATTERY_PATH/capacity")
 last_ac_status=$(cat "$AC_PATH/online" 2>/dev/null || echo "0")
+low_id=""
 
 while true; do
     level=$(cat "$BATTERY_PATH/capacity")
     ac_status=$(cat "$AC_PATH/online" 2>/dev/null || echo "0")
     
     if [ "$ac_status" -eq 1 ] && [ "$last_ac_status" -eq 0 ]; then
+        [ -n "$low_id" ] && pino --close "$low_id" 2>/dev/null
+        low_id=""
         pino -t "Plugged In" -m "Now charging ($level%)" -d 5
 
     elif [ "$ac_status" -eq 0 ] && [ "$last_ac_status" -eq 1 ]; then
@@ -25,7 +33,7 @@ while true; do
         pino -t "Battery High" -m "Reached $level%" -d 5
     
     elif [ "$level" -le $DOWN_THRESHOLD ] && [ "$last_level" -gt $DOWN_THRESHOLD ] && [ "$ac_status" -eq 0 ]; then
-        pino -t "Battery Low" -m "Only $level% left" -d 5
+        low_id=$(pino -t "Battery Low" -m "Only $level% left" -d 5 --print-id)
     fi
     
     last_level=$level
@@ -1,6 +1,12 @@
-use serde::Deserialize;
 use std::fs;
-use std::{env, fs::OpenOptions, io::Write, path::PathBuf};
+use std::{
+    collections::HashMap,
+    env,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
 
 pub fn get_config_dir() -> PathBuf {
     if let Ok(xdg_config) = env::var("XDG_CONFIG_HOME") {
@@ -12,25 +18,17 @@ pub fn get_config_dir() -> PathBuf {
     panic!("Could not determine config directory");
 }
 
-fn create_template(app: &str) {
-    let config = get_config_dir()
-        .join(app)
-        .join("templates")
-        .join("colors-pino.toml");
-
-    fs::create_dir_all(config.parent().unwrap())
-        .expect("Failed to create directories for template");
-    println!("Creating template at: {:?}", config);
+fn create_template(config: &Path) -> io::Result<()> {
+    fs::create_dir_all(config.parent().unwrap())?;
+    eprintln!("Creating template at: {:?}", config);
     let mut template = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(&config)
-        .expect("Can't Create Template File !!!");
+        .open(config)?;
 
-    template
-        .write_all(
-            b"bg = \"{background}\"
+    template.write_all(
+        b"bg = \"{background}\"
 fg = \"{foreground}\"
 
 color0  = \"{color0}\"
@@ -50,88 +48,636 @@ color13 = \"{color13}\"
 color14 = \"{color14}\"
 color15 = \"{color15}\"
 ",
-        )
-        .expect("Can't Create Template File !!!");
-}
-
-#[derive(Debug, Deserialize)]
-struct Pywal {
-    bg: String,
-    fg: String,
-    color0: String,
-    color1: String,
-    color2: String,
-    color3: String,
-    color4: String,
-    color5: String,
-    color6: String,
-    color7: String,
-    color8: String,
-    color9: String,
-    color10: String,
-    color11: String,
-    color12: String,
-    color13: String,
-    color14: String,
-    color15: String,
-}
-
-fn get_color<'a>(colors: &'a Pywal, key: &str) -> &'a str {
-    match key {
-        "bg" => &colors.bg,
-        "fg" => &colors.fg,
-        "color0" => &colors.color0,
-        "color1" => &colors.color1,
-        "color2" => &colors.color2,
-        "color3" => &colors.color3,
-        "color4" => &colors.color4,
-        "color5" => &colors.color5,
-        "color6" => &colors.color6,
-        "color7" => &colors.color7,
-        "color8" => &colors.color8,
-        "color9" => &colors.color9,
-        "color10" => &colors.color10,
-        "color11" => &colors.color11,
-        "color12" => &colors.color12,
-        "color13" => &colors.color13,
-        "color14" => &colors.color14,
-        "color15" => &colors.color15,
-        _ => panic!("Invalid key!"),
-    }
-}
-
-pub fn pywal(
-    background_color: String,
-    border_color: String,
-    title_color: String,
-    message_color: String,
-) -> (String, String, String, String) {
-    let home = env::var("HOME").expect("Can't Find Home Dir");
-
-    if !get_config_dir()
-        .join("wal/templates/colors-pino.toml")
-        .exists()
-    {
-        create_template("wal");
+    )
+}
+
+// Writes the colors-pino.toml template of wal and walrs where it is missing, so they
+// write the palette on their next run. Returns what couldn't be written.
+pub fn create_templates() -> Vec<String> {
+    let mut problems = Vec::new();
+    for app in ["wal", "walrs"] {
+        let template = get_config_dir()
+            .join(app)
+            .join("templates")
+            .join("colors-pino.toml");
+        if !template.exists()
+            && let Err(e) = create_template(&template)
+        {
+            problems.push(format!("Can't create {}: {e}", template.display()));
+        }
     }
+    problems
+}
 
-    if !get_config_dir()
-        .join("walrs/templates/colors-pino.toml")
-        .exists()
-    {
-        create_template("walrs");
-    }
-
-    let cache_colors = PathBuf::from(home)
-        .join(".cache")
-        .join("wal")
-        .join("colors-pino.toml");
-    let content = fs::read_to_string(&cache_colors).expect("Can't Read Colors File!!!");
-    let colors: Pywal = toml::from_str(&content).expect("Invalid TOML Format!!!");
-    (
-        get_color(&colors, background_color.as_str()).to_string(),
-        get_color(&colors, border_color.as_str()).to_string(),
-        get_color(&colors, title_color.as_str()).to_string(),
-        get_color(&colors, message_color.as_str()).to_string(),
+// A color with its channels from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgba {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    pub a: f64,
+}
+
+impl Default for Rgba {
+    fn default() -> Rgba {
+        Rgba {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            a: 1.0,
+        }
+    }
+}
+
+// CSS colors that can be used by name.
+const NAMED: [(&str, &str); 22] = [
+    ("black", "#000000"),
+    ("white", "#ffffff"),
+    ("gray", "#808080"),
+    ("grey", "#808080"),
+    ("silver", "#c0c0c0"),
+    ("red", "#ff0000"),
+    ("maroon", "#800000"),
+    ("orange", "#ffa500"),
+    ("yellow", "#ffff00"),
+    ("olive", "#808000"),
+    ("lime", "#00ff00"),
+    ("green", "#008000"),
+    ("teal", "#008080"),
+    ("cyan", "#00ffff"),
+    ("aqua", "#00ffff"),
+    ("blue", "#0000ff"),
+    ("navy", "#000080"),
+    ("purple", "#800080"),
+    ("magenta", "#ff00ff"),
+    ("fuchsia", "#ff00ff"),
+    ("pink", "#ffc0cb"),
+    ("transparent", "#00000000"),
+];
+
+impl Rgba {
+    // "#rgb", "#rgba", "#rrggbb" or "#rrggbbaa".
+    pub fn from_hex(value: &str) -> Option<Rgba> {
+        let digits = value.trim().strip_prefix('#')?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digits: String = match digits.len() {
+            3 | 4 => digits.chars().flat_map(|c| [c, c]).collect(),
+            6 | 8 => digits.to_string(),
+            _ => return None,
+        };
+        let channel = |i: usize| {
+            digits.get(i..i + 2).map_or(Some(1.0), |hex| {
+                Some(u8::from_str_radix(hex, 16).ok()? as f64 / 255.0)
+            })
+        };
+        Some(Rgba {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: channel(6)?,
+        })
+    }
+
+    // #rrggbb, with the alpha after it unless the color is opaque.
+    pub fn to_hex(self) -> String {
+        let (r, g, b) = self.rgb8();
+        let alpha = (self.a * 255.0).round() as u8;
+        match alpha {
+            255 => format!("#{r:02x}{g:02x}{b:02x}"),
+            _ => format!("#{r:02x}{g:02x}{b:02x}{alpha:02x}"),
+        }
+    }
+
+    pub fn rgb8(self) -> (u8, u8, u8) {
+        let byte = |channel: f64| (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+        (byte(self.r), byte(self.g), byte(self.b))
+    }
+
+    // Hue in degrees, saturation and lightness from 0 to 1.
+    fn hsl(self) -> (f64, f64, f64) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+        if d == 0.0 {
+            return (0.0, 0.0, l);
+        }
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == self.r {
+            ((self.g - self.b) / d).rem_euclid(6.0)
+        } else if max == self.g {
+            (self.b - self.r) / d + 2.0
+        } else {
+            (self.r - self.g) / d + 4.0
+        };
+        (h * 60.0, s, l)
+    }
+
+    fn from_hsl(h: f64, s: f64, l: f64, a: f64) -> Rgba {
+        let (s, l) = (s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u8 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = l - c / 2.0;
+        Rgba {
+            r: r + m,
+            g: g + m,
+            b: b + m,
+            a,
+        }
+    }
+
+    // `self` with `amount` (0 to 1) of `other` mixed in, alpha included.
+    fn mix(self, other: Rgba, amount: f64) -> Rgba {
+        let mix = |a: f64, b: f64| a + (b - a) * amount;
+        Rgba {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: mix(self.a, other.a),
+        }
+    }
+
+    // The opaque color seen when `self` is drawn over `below`.
+    pub fn over(self, below: Rgba) -> Rgba {
+        Rgba {
+            a: 1.0,
+            ..below.mix(Rgba { a: 1.0, ..self }, self.a)
+        }
+    }
+}
+
+// A color expression: "#rgb", "#rrggbbaa", rgb()/rgba()/hsl()/hsla(), a color name or
+// palette name, or lighten(), darken(), mix() and alpha() of another expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Color(Rgba),
+    Name(String),
+    // darkens for a negative amount
+    Lighten(Box<Expr>, f64),
+    Mix(Box<Expr>, Box<Expr>, f64),
+    Alpha(Box<Expr>, f64),
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.at..]
+    }
+
+    fn skip_space(&mut self) {
+        self.at = self.text.len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        let found = self.rest().starts_with(c);
+        if found {
+            self.at += c.len_utf8();
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        match self.rest().chars().next() {
+            Some(found) => Err(format!("expected `{c}` but found `{found}`")),
+            None => Err(format!("expected `{c}` at the end")),
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        self.skip_space();
+        let start = self.at;
+        let len = self.rest().find(|c| !f(c)).unwrap_or(self.rest().len());
+        self.at += len;
+        &self.text[start..self.at]
+    }
+
+    // A number and whether it ended in %.
+    fn number(&mut self) -> Result<(f64, bool), String> {
+        let text = self.take_while(|c| c.is_ascii_digit() || c == '.' || c == '-');
+        let number = text
+            .parse()
+            .map_err(|_| format!("expected a number but found `{text}`"))?;
+        Ok((number, self.eat('%')))
+    }
+
+    // An amount from 0 to 1, written as 0.2 or 20%.
+    fn amount(&mut self) -> Result<f64, String> {
+        let (number, percent) = self.number()?;
+        let amount = if percent { number / 100.0 } else { number };
+        if !(0.0..=1.0).contains(&amount) {
+            let written = if percent { "%" } else { "" };
+            return Err(format!(
+                "`{number}{written}` is not an amount from 0 to 1 or 0% to 100%"
+            ));
+        }
+        Ok(amount)
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        if self.eat('#') {
+            let digits = self.take_while(|c| c.is_ascii_alphanumeric());
+            return Rgba::from_hex(&format!("#{digits}"))
+                .map(Expr::Color)
+                .ok_or(format!(
+                    "`#{digits}` is not a #rgb, #rgba, #rrggbb or #rrggbbaa color"
+                ));
+        }
+        let name = self
+            .take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            .to_string();
+        if name.is_empty() {
+            return Err(match self.rest().chars().next() {
+                Some(found) => format!("expected a color but found `{found}`"),
+                None => "expected a color".to_string(),
+            });
+        }
+        if !self.eat('(') {
+            return Ok(Expr::Name(name));
+        }
+        let expr = match name.to_ascii_lowercase().as_str() {
+            function @ ("rgb" | "rgba") => {
+                let mut channels = [0.0; 3];
+                for (i, channel) in channels.iter_mut().enumerate() {
+                    if i > 0 {
+                        self.expect(',')?;
+                    }
+                    let (number, percent) = self.number()?;
+                    *channel = if percent {
+                        number / 100.0
+                    } else {
+                        number / 255.0
+                    };
+                }
+                let a = if function == "rgba" || self.eat(',') {
+                    if function == "rgba" {
+                        self.expect(',')?;
+                    }
+                    self.amount()?
+                } else {
+                    1.0
+                };
+                let [r, g, b] = channels.map(|channel| channel.clamp(0.0, 1.0));
+                Expr::Color(Rgba { r, g, b, a })
+            }
+            function @ ("hsl" | "hsla") => {
+                let (h, _) = self.number()?;
+                self.expect(',')?;
+                let s = self.amount()?;
+                self.expect(',')?;
+                let l = self.amount()?;
+                let a = if function == "hsla" || self.eat(',') {
+                    if function == "hsla" {
+                        self.expect(',')?;
+                    }
+                    self.amount()?
+                } else {
+                    1.0
+                };
+                Expr::Color(Rgba::from_hsl(h, s, l, a))
+            }
+            function @ ("lighten" | "darken") => {
+                let color = self.expr()?;
+                self.expect(',')?;
+                let amount = self.amount()?;
+                let amount = if function == "darken" {
+                    -amount
+                } else {
+                    amount
+                };
+                Expr::Lighten(Box::new(color), amount)
+            }
+            "mix" => {
+                let color = self.expr()?;
+                self.expect(',')?;
+                let other = self.expr()?;
+                let amount = if self.eat(',') { self.amount()? } else { 0.5 };
+                Expr::Mix(Box::new(color), Box::new(other), amount)
+            }
+            "alpha" => {
+                let color = self.expr()?;
+                self.expect(',')?;
+                Expr::Alpha(Box::new(color), self.amount()?)
+            }
+            _ => return Err(format!("`{name}()` is not a color function")),
+        };
+        self.expect(')')?;
+        Ok(expr)
+    }
+}
+
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser { text, at: 0 };
+    let expr = parser.expr()?;
+    parser.skip_space();
+    match parser.rest() {
+        "" => Ok(expr),
+        rest => Err(format!("unexpected `{rest}` after the color")),
+    }
+}
+
+impl Expr {
+    // The color, with names looked up by `lookup` first and among the CSS names then.
+    pub fn eval(&self, lookup: &dyn Fn(&str) -> Option<Rgba>) -> Result<Rgba, String> {
+        Ok(match self {
+            Expr::Color(color) => *color,
+            Expr::Name(name) => lookup(name)
+                .or_else(|| {
+                    let name = name.to_ascii_lowercase();
+                    NAMED
+                        .iter()
+                        .find(|(named, _)| *named == name)
+                        .and_then(|(_, hex)| Rgba::from_hex(hex))
+                })
+                .ok_or(format!("no color is named `{name}`"))?,
+            Expr::Lighten(color, amount) => {
+                let color = color.eval(lookup)?;
+                let (h, s, l) = color.hsl();
+                Rgba::from_hsl(h, s, l + amount, color.a)
+            }
+            Expr::Mix(color, other, amount) => {
+                color.eval(lookup)?.mix(other.eval(lookup)?, *amount)
+            }
+            Expr::Alpha(color, a) => Rgba {
+                a: *a,
+                ..color.eval(lookup)?
+            },
+        })
+    }
+}
+
+// Colors by name, as a theme source defines them.
+pub type Palette = HashMap<String, Rgba>;
+
+// What `[theme] source` can be.
+pub const SOURCES: [&str; 5] = ["pywal", "walrs", "base16", "xresources", "gtk"];
+
+// Where wal and walrs write the colors of the current wallpaper.
+pub fn cache_file() -> Option<PathBuf> {
+    let home = env::var("HOME").ok()?;
+    Some(
+        PathBuf::from(home)
+            .join(".cache")
+            .join("wal")
+            .join("colors-pino.toml"),
     )
 }
+
+// The file a source is read from when `[theme] file` isn't set. base16 has no
+// default, and Xresources are asked from xrdb before this file is read.
+pub fn default_file(source: &str) -> Option<PathBuf> {
+    let home = env::var("HOME").ok().map(PathBuf::from);
+    match source {
+        "pywal" | "walrs" => cache_file(),
+        "xresources" => home.map(|home| home.join(".Xresources")),
+        "gtk" => Some(get_config_dir().join("gtk-3.0").join("gtk.css")),
+        _ => None,
+    }
+}
+
+// The color of "#rgb", "#rrggbb", "rrggbb" (base16) and "rgb:rr/gg/bb" (X11).
+fn hex(value: &str) -> Option<Rgba> {
+    let value = value.trim();
+    match value.strip_prefix("rgb:") {
+        Some(rgb) => {
+            let parts: Vec<&str> = rgb.split('/').collect();
+            if parts.len() != 3 || parts.iter().any(|part| part.len() != 2) {
+                return None;
+            }
+            Rgba::from_hex(&format!("#{}", parts.concat()))
+        }
+        None if value.len() == 6 => Rgba::from_hex(&format!("#{value}")),
+        None => Rgba::from_hex(value),
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Can't read {}: {e}", path.display()))
+}
+
+// The colors-pino.toml that wal and walrs write from the template: bg, fg and color0-15.
+fn pywal(path: &Path) -> Result<Palette, String> {
+    let colors: HashMap<String, String> =
+        toml::from_str(&read(path)?).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(colors
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, hex(&value)?)))
+        .collect())
+}
+
+// A base16 scheme: base00-base0F at the top level, or under `palette` in the newer
+// tinted-theming files.
+fn base16(path: &Path) -> Result<Palette, String> {
+    let scheme: serde_yaml::Value =
+        serde_yaml::from_str(&read(path)?).map_err(|e| format!("{}: {e}", path.display()))?;
+    let colors = scheme.get("palette").unwrap_or(&scheme);
+    let Some(colors) = colors.as_mapping() else {
+        return Err(format!("{} is not a base16 scheme", path.display()));
+    };
+    Ok(colors
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str()?.to_string(), hex(value.as_str()?)?)))
+        .collect())
+}
+
+// Resources like `*.color4: #81a2be` or `URxvt.background: #1d1f21`, named by their
+// last component. Colors set through a #define are followed.
+fn xresources(content: &str) -> Palette {
+    let mut defines = HashMap::new();
+    let mut palette = Palette::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                defines.insert(name.to_string(), value.to_string());
+            }
+            continue;
+        }
+        if line.starts_with('!') || line.starts_with('#') {
+            continue;
+        }
+        let Some((resource, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = resource
+            .rsplit(['.', '*'])
+            .next()
+            .unwrap_or_default()
+            .trim();
+        let value = value.trim();
+        let value = defines.get(value).map_or(value, String::as_str);
+        if let (false, Some(color)) = (name.is_empty(), hex(value)) {
+            palette.insert(name.to_string(), color);
+        }
+    }
+    palette
+}
+
+// The resources of the X server, or the file when there is no X server to ask.
+fn x_resources(file: Option<&Path>) -> Result<Palette, String> {
+    if file.is_none()
+        && let Ok(output) = process::Command::new("xrdb").arg("-query").output()
+        && output.status.success()
+    {
+        return Ok(xresources(&String::from_utf8_lossy(&output.stdout)));
+    }
+    let path = file
+        .map(Path::to_path_buf)
+        .or_else(|| default_file("xresources"))
+        .ok_or("Can't find the home directory")?;
+    Ok(xresources(&read(&path)?))
+}
+
+// `@define-color name value;` of a GTK style sheet. Values are color expressions
+// with `@another_name` for the other colors, like `alpha(@theme_fg_color, 0.5)`.
+fn gtk(path: &Path) -> Result<Palette, String> {
+    let content = read(path)?;
+    let mut defined = HashMap::new();
+    for statement in content.split(';') {
+        let Some((_, define)) = statement.split_once("@define-color") else {
+            continue;
+        };
+        if let Some((name, value)) = define.trim().split_once(char::is_whitespace)
+            && let Ok(expr) = parse(&value.replace('@', ""))
+        {
+            defined.insert(name.to_string(), expr);
+        }
+    }
+    // Names are followed as deep as there are names, so a circle ends somewhere.
+    fn resolve(name: &str, defined: &HashMap<String, Expr>, depth: usize) -> Option<Rgba> {
+        if depth > defined.len() {
+            return None;
+        }
+        let lookup = |other: &str| resolve(other, defined, depth + 1);
+        defined.get(name)?.eval(&lookup).ok()
+    }
+    Ok(defined
+        .keys()
+        .filter_map(|name| Some((name.clone(), resolve(name, &defined, 0)?)))
+        .collect())
+}
+
+// Reads the palette of `source` from `file`, or from where the source keeps it.
+pub fn palette(source: &str, file: Option<&Path>) -> Result<Palette, String> {
+    let path = || {
+        file.map(Path::to_path_buf)
+            .or_else(|| default_file(source))
+            .ok_or(format!("The {source} theme needs a `[theme] file`"))
+    };
+    match source {
+        "pywal" | "walrs" => pywal(&path()?),
+        "base16" => base16(&path()?),
+        "xresources" => x_resources(file),
+        "gtk" => gtk(&path()?),
+        _ => Err(format!(
+            "`theme.source` is not one of {}: {source}",
+            SOURCES.join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The hex of `text`, with `accent` as the only palette name.
+    fn color(text: &str) -> Result<String, String> {
+        let accent = Rgba::from_hex("#81a2be");
+        let lookup = |name: &str| (name == "accent").then_some(accent).flatten();
+        Ok(parse(text)?.eval(&lookup)?.to_hex())
+    }
+
+    #[test]
+    fn parses_hex() {
+        assert_eq!(color("#fff").unwrap(), "#ffffff");
+        assert_eq!(color(" #1D1F21 ").unwrap(), "#1d1f21");
+        assert_eq!(color("#0008").unwrap(), "#00000088");
+        assert_eq!(color("#11223344").unwrap(), "#11223344");
+        assert!(color("#12345").is_err());
+        assert!(color("#ggg").is_err());
+    }
+
+    #[test]
+    fn parses_functions() {
+        assert_eq!(color("rgb(255, 0, 50%)").unwrap(), "#ff0080");
+        assert_eq!(color("rgba(0,0,0,0.5)").unwrap(), "#00000080");
+        assert_eq!(color("hsl(0, 100%, 50%)").unwrap(), "#ff0000");
+        assert_eq!(color("hsla(120, 1, 0.25, 1)").unwrap(), "#008000");
+        assert_eq!(color("lighten(black, 50%)").unwrap(), "#808080");
+        assert_eq!(color("darken(white, 0.25)").unwrap(), "#bfbfbf");
+        assert_eq!(color("mix(black, white)").unwrap(), "#808080");
+        assert_eq!(color("mix(black, white, 0)").unwrap(), "#000000");
+        assert_eq!(color("alpha(red, 20%)").unwrap(), "#ff000033");
+        assert_eq!(
+            color("MIX(accent, Transparent, 100%)").unwrap(),
+            "#00000000"
+        );
+    }
+
+    #[test]
+    fn amounts_are_fractions_or_percents() {
+        assert_eq!(color("alpha(red, 1)").unwrap(), "#ff0000");
+        assert_eq!(color("alpha(red, 100%)").unwrap(), "#ff0000");
+        assert_eq!(color("alpha(red, 0%)").unwrap(), "#ff000000");
+        for amount in ["20", "1.5", "150%", "-0.1"] {
+            let text = format!("alpha(red, {amount})");
+            assert!(color(&text).is_err(), "{text}");
+        }
+        assert!(color("lighten(red, 10)").is_err());
+    }
+
+    #[test]
+    fn looks_up_names() {
+        assert_eq!(color("accent").unwrap(), "#81a2be");
+        assert_eq!(color("Navy").unwrap(), "#000080");
+        assert_eq!(
+            color("nope").unwrap_err(),
+            "no color is named `nope`".to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_expressions() {
+        assert!(color("").is_err());
+        assert!(color("red blue").is_err());
+        assert!(color("rgb(1, 2)").is_err());
+        assert!(color("mix(red, blue").is_err());
+        assert!(color("shade(red, 0.1)").is_err());
+    }
+
+    #[test]
+    fn blends_over_background() {
+        let white = Rgba::from_hex("#ffffff80").unwrap();
+        let black = Rgba::from_hex("#000").unwrap();
+        assert_eq!(white.over(black).to_hex(), "#808080");
+    }
+
+    #[test]
+    fn reads_xresources() {
+        let palette = xresources(
+            "! comment\n#define blue #81a2be\n*.color4: blue\nURxvt.background: #1d1f21\n*foreground: nope\n",
+        );
+        assert_eq!(palette.len(), 2);
+        assert_eq!(palette["color4"].to_hex(), "#81a2be");
+        assert_eq!(palette["background"].to_hex(), "#1d1f21");
+    }
+}
@@ -1,24 +1,37 @@
+use crate::{colors, protocol::Urgency as Level, rules, ui};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::{OpenOptions, create_dir_all},
-    io::Write,
-    path::PathBuf,
+    collections::HashMap,
+    env,
+    fs::{self, OpenOptions, create_dir_all},
+    io::{self, Write},
+    path::{Path, PathBuf},
 };
+use toml::Value;
 
 pub fn generate_config(config_path: PathBuf) {
     let config = config_path.join("pino").join("config.toml");
 
-    create_dir_all(config.parent().unwrap()).expect("Failed to create directories for template");
     println!("Creating config file at: {}", config.display());
+    if let Err(e) = write_template(&config) {
+        eprintln!("Can't create {}: {e}", config.display());
+    }
+}
+
+fn write_template(config: &Path) -> io::Result<()> {
+    create_dir_all(config.parent().unwrap())?;
     let mut template = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(&config)
-        .expect("Can't Create Template File !!!");
+        .open(config)?;
 
-    template
-        .write_all(
-            b"[screen]
+    template.write_all(
+        b"# Read other files first, so this one only has to change what differs from them:
+# include = [\"theme.toml\", \"~/dotfiles/pino.toml\"]
+
+[screen]
 monitor = 0 # Set the monitor using index
 
 # Placment: 
@@ -34,6 +47,15 @@ height = 100    # Height of the app
 
 delay = 5 # Time to display the app (in seconds)
 
+gap = 10         # Space between stacked notifications
+max_visible = 5  # Extra notifications wait until one of these closes
+
+auto_height = false  # Grow the height to fit the message
+max_height = 300     # Up to this height
+
+# Colors are #rgb, #rrggbb or #rrggbbaa, rgb(), hsl(), names like \"white\" or a palette
+# color, and lighten(c, 10%), darken(c, 10%), mix(a, b, 25%) or alpha(c, 0.8) of those.
+# The alpha of fg_color makes the whole notification translucent (with a compositor).
 [frame]
 fg_color = \"#1a1e24\" 
 
@@ -56,7 +78,14 @@ color = \"#626977\"
 font_size = 15
 x = 10
 y = 45
+max_lines = 0  # Cut longer messages with an ellipsis (0 = no limit)
+
+# Where the palette colors come from, e.g. color = \"darken(color4, 10%)\"
+[theme]
+# source = \"xresources\"  # pywal, walrs, base16, xresources or gtk
+# file = \"~/.Xresources\" # Defaults to where the source keeps its colors (base16 needs one)
 
+# Same as source = \"pywal\", with these names for the background, border, title and message
 [pywal]
 pywal = false
 
@@ -67,7 +96,930 @@ border_color      = \"color1\"
 title_color       = \"fg\"
 message_color     = \"color8\"
 
+[icon]
+size = 48             # Icons are scaled to fit in size x size
+position = \"left\"     # \"left\" or \"right\" of the text
+x = 10
+y = 0
+theme = \"hicolor\"     # Icon theme used to resolve names like \"battery-low\"
+
+# Drawn when a notification has a value (pino -p 0-100)
+[progress]
+color = \"#c5c6c8\"             # Defaults to the title color
+background_color = \"#ffffff\"  # Defaults to the border color
+height = 6
+x = 10
+y = -8    # Negative values count from the bottom
+radius = 3
+
+# Per-urgency overrides (pino -u low|normal|critical). Every key is optional:
+#   background_color, border_color, title_color, message_color,
+#   border_weight, border_radius, title_size, message_size, delay, sound
+# delay = 0 keeps the notification until it is clicked (the default for critical)
+[urgency.low]
+delay = 3
+
+[urgency.critical]
+border_color = \"#e06c75\"
+delay = 0
+
+# Closed notifications are kept in $XDG_STATE_HOME/pino/history.jsonl (pino history)
+[history]
+limit = 1000  # Entries to keep, 0 turns the history off
+
+# pino --dnd on|off|toggle|status
+[dnd]
+bypass_critical = true  # Critical notifications are shown anyway
+on_end = \"summary\"     # \"summary\" shows what arrived in one notification, \"discard\" drops it
+
+# Rules match on app, title, body (regular expressions), urgency and session, then can
+# suppress = true, set_title / set_body ($1 are the groups, $0 the match), or change
+# delay, sound, placement and style = { ...the keys of an [urgency.*] section... }
+[[rule]]
+app = \"(?i)discord\"
+set_title = \"Discord: $0\"
+
+[optional]
+sound = false                        # Play a sound when a notification shows up (needs the sound feature)
+sound_file = \"message-new-instant\"   # A WAV/Ogg file or a freedesktop sound theme name
+reload_visible = true                # Restyle the notifications on screen when the config changes
+
 ",
-        )
-        .expect("Can't Create Template File !!!");
+    )
+}
+
+// Every section and key is optional. What is left out takes the defaults of the
+// template above.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub screen: Screen,
+    pub frame: Frame,
+    pub border: Border,
+    pub title: Title,
+    pub message: Message,
+    pub theme: Theme,
+    pub pywal: Pywal,
+    pub urgency: Urgency,
+    pub icon: Icon,
+    pub progress: Progress,
+    pub history: History,
+    pub dnd: Dnd,
+    pub rule: Vec<Rule>,
+    pub optional: Optional,
+    // more files, read before this one so it can override them
+    #[serde(skip_serializing)]
+    pub include: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Screen {
+    pub monitor: Option<i32>,
+    pub placement: Option<String>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub delay: Option<u64>,
+    pub gap: Option<i32>,
+    pub max_visible: Option<usize>,
+    pub auto_height: Option<bool>,
+    pub max_height: Option<i32>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Frame {
+    pub fg_color: Option<String>,
+    pub font_family: Option<String>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Border {
+    pub weight: Option<i32>,
+    pub color: Option<String>,
+    pub radius: Option<i32>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Title {
+    pub color: Option<String>,
+    pub font_size: Option<i32>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Message {
+    pub color: Option<String>,
+    pub font_size: Option<i32>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub max_lines: Option<usize>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Pywal {
+    pub pywal: Option<bool>,
+    pub background_color: Option<String>,
+    pub border_color: Option<String>,
+    pub title_color: Option<String>,
+    pub message_color: Option<String>,
+}
+impl Pywal {
+    fn colors(&mut self) -> Vec<(String, &mut Option<String>)> {
+        vec![
+            (
+                "pywal.background_color".to_string(),
+                &mut self.background_color,
+            ),
+            ("pywal.border_color".to_string(), &mut self.border_color),
+            ("pywal.title_color".to_string(), &mut self.title_color),
+            ("pywal.message_color".to_string(), &mut self.message_color),
+        ]
+    }
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Theme {
+    pub source: Option<String>,
+    pub file: Option<String>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Icon {
+    pub size: Option<i32>,
+    pub position: Option<String>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub theme: Option<String>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Progress {
+    pub color: Option<String>,
+    pub background_color: Option<String>,
+    pub height: Option<i32>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub radius: Option<i32>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Dnd {
+    pub bypass_critical: Option<bool>,
+    pub on_end: Option<String>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    pub limit: Option<usize>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Optional {
+    pub sound: Option<bool>,
+    pub sound_file: Option<String>,
+    pub reload_visible: Option<bool>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Urgency {
+    pub low: Option<StyleOverride>,
+    pub normal: Option<StyleOverride>,
+    pub critical: Option<StyleOverride>,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Rule {
+    pub app: Option<String>,
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub urgency: Option<Level>,
+    pub session: Option<u8>,
+    pub suppress: Option<bool>,
+    pub set_title: Option<String>,
+    pub set_body: Option<String>,
+    pub delay: Option<u64>,
+    pub sound: Option<String>,
+    pub placement: Option<String>,
+    pub style: Option<StyleOverride>,
+}
+
+// The part of a style an `[urgency.*]` section or a rule can change.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StyleOverride {
+    background_color: Option<String>,
+    border_color: Option<String>,
+    title_color: Option<String>,
+    message_color: Option<String>,
+    border_weight: Option<i32>,
+    border_radius: Option<i32>,
+    title_size: Option<i32>,
+    message_size: Option<i32>,
+    delay: Option<u64>,
+    sound: Option<String>,
+}
+
+impl StyleOverride {
+    pub fn apply(&self, style: &mut ui::Style) {
+        let colors = &mut style.colors;
+        for (color, value) in [
+            (&mut colors.0, &self.background_color),
+            (&mut colors.1, &self.border_color),
+            (&mut colors.2, &self.title_color),
+            (&mut colors.3, &self.message_color),
+        ] {
+            if let Some(value) = value {
+                *color = value.clone();
+            }
+        }
+        style.border.0 = self.border_weight.unwrap_or(style.border.0);
+        style.border.1 = self.border_radius.unwrap_or(style.border.1);
+        style.title.2 = self.title_size.unwrap_or(style.title.2);
+        style.message.2 = self.message_size.unwrap_or(style.message.2);
+        style.delay = self.delay.unwrap_or(style.delay);
+        if let Some(sound) = &self.sound {
+            style.sound = sound.clone();
+        }
+    }
+
+    fn colors(&mut self, section: &str) -> Vec<(String, &mut Option<String>)> {
+        vec![
+            (
+                format!("{section}.background_color"),
+                &mut self.background_color,
+            ),
+            (format!("{section}.border_color"), &mut self.border_color),
+            (format!("{section}.title_color"), &mut self.title_color),
+            (format!("{section}.message_color"), &mut self.message_color),
+        ]
+    }
+}
+
+impl Rule {
+    pub fn compile(&self) -> Result<rules::Rule, regex::Error> {
+        let pattern = |pattern: &Option<String>| pattern.as_deref().map(Regex::new).transpose();
+        Ok(rules::Rule {
+            app: pattern(&self.app)?,
+            title: pattern(&self.title)?,
+            body: pattern(&self.body)?,
+            urgency: self.urgency,
+            session: self.session,
+            suppress: self.suppress.unwrap_or(false),
+            set_title: self.set_title.clone(),
+            set_body: self.set_body.clone(),
+            delay: self.delay,
+            sound: self.sound.clone(),
+            placement: self.placement.clone(),
+            style: self.style.clone(),
+        })
+    }
+}
+
+// `path` with a leading `~/` standing for the home directory.
+fn home_path(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(env::var("HOME").unwrap_or_default()).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+impl Config {
+    // Where color keys find the colors they name: `[theme] source`, or pywal for
+    // `[pywal] pywal = true`.
+    fn theme_source(&self) -> Option<&str> {
+        let pywal = self.pywal.pywal == Some(true);
+        self.theme.source.as_deref().or(pywal.then_some("pywal"))
+    }
+
+    // The file the palette is read from, to watch it for changes.
+    pub fn theme_file(&self) -> Option<PathBuf> {
+        let source = self.theme_source()?;
+        match &self.theme.file {
+            Some(file) => Some(home_path(file)),
+            None => colors::default_file(source),
+        }
+    }
+}
+
+// Puts the colors of the expressions in place of them, `[pywal]` ones included, with
+// names looked up in the palette. Expressions that can't be worked out leave their key
+// to the default, and a palette that can't be read leaves the `[pywal]` colors to the
+// ones of the config.
+fn resolve_colors(config: &mut Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    let source = config.theme_source().map(String::from);
+    if matches!(source.as_deref(), Some("pywal" | "walrs")) {
+        problems.extend(colors::create_templates());
+    }
+    let palette = source.as_deref().and_then(|source| {
+        let file = config.theme.file.as_deref().map(home_path);
+        colors::palette(source, file.as_deref())
+            .map_err(|e| problems.push(format!("{e}, using the colors of the config")))
+            .ok()
+    });
+    // Names of a palette that couldn't be read were reported with it.
+    let quiet = source.is_some() && palette.is_none();
+    let palette = palette.unwrap_or_default();
+    let mut eval = |key: &str, value: &str| {
+        let lookup = |name: &str| palette.get(name).copied();
+        let color = colors::parse(value).and_then(|expr| expr.eval(&lookup));
+        match (color, &source) {
+            (Ok(color), _) => Some(color.to_hex()),
+            (Err(_), _) if quiet => None,
+            (Err(e), Some(source)) => {
+                problems.push(format!("`{key}`: {e} in the {source} palette"));
+                None
+            }
+            (Err(e), None) => {
+                problems.push(format!("`{key}`: {e}, and there is no `[theme] source`"));
+                None
+            }
+        }
+    };
+
+    if config.pywal.pywal == Some(true) && !quiet {
+        let pywal = &config.pywal;
+        for (key, color, value, default) in [
+            (
+                "pywal.background_color",
+                &mut config.frame.fg_color,
+                &pywal.background_color,
+                "bg",
+            ),
+            (
+                "pywal.border_color",
+                &mut config.border.color,
+                &pywal.border_color,
+                "color1",
+            ),
+            (
+                "pywal.title_color",
+                &mut config.title.color,
+                &pywal.title_color,
+                "fg",
+            ),
+            (
+                "pywal.message_color",
+                &mut config.message.color,
+                &pywal.message_color,
+                "color8",
+            ),
+        ] {
+            let value = value.as_deref().unwrap_or(default);
+            if let Some(value) = eval(key, value) {
+                *color = Some(value);
+            }
+        }
+    }
+    for (key, value) in colors(config) {
+        if let Some(expr) = value.take() {
+            *value = eval(&key, &expr);
+        }
+    }
+    problems
+}
+
+// Every color key of the config, with its name.
+fn colors(config: &mut Config) -> Vec<(String, &mut Option<String>)> {
+    let mut colors = vec![
+        ("frame.fg_color".to_string(), &mut config.frame.fg_color),
+        ("border.color".to_string(), &mut config.border.color),
+        ("title.color".to_string(), &mut config.title.color),
+        ("message.color".to_string(), &mut config.message.color),
+        ("progress.color".to_string(), &mut config.progress.color),
+        (
+            "progress.background_color".to_string(),
+            &mut config.progress.background_color,
+        ),
+    ];
+    let urgency = &mut config.urgency;
+    for (name, style) in [
+        ("low", &mut urgency.low),
+        ("normal", &mut urgency.normal),
+        ("critical", &mut urgency.critical),
+    ] {
+        if let Some(style) = style {
+            colors.extend(style.colors(&format!("urgency.{name}")));
+        }
+    }
+    for (i, rule) in config.rule.iter_mut().enumerate() {
+        if let Some(style) = &mut rule.style {
+            colors.extend(style.colors(&format!("rule.{i}.style")));
+        }
+    }
+    colors
+}
+
+fn split_key(key: &str) -> Vec<String> {
+    key.split('.')
+        .map(|part| part.trim().trim_matches('"').to_string())
+        .collect()
+}
+
+// The line (from 1) that sets `key`, following the table headers down to it. Array
+// tables count from 0 like in the key, e.g. rule.1.title.
+fn line_of(content: &str, key: &[&str]) -> Option<usize> {
+    let mut table: Vec<String> = Vec::new();
+    let mut arrays: HashMap<String, usize> = HashMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        let path = if let Some(header) = line.strip_prefix("[[") {
+            let name = header.split("]]").next().unwrap_or_default();
+            let count = arrays.entry(name.trim().to_string()).or_insert(0);
+            table = split_key(name);
+            table.push(count.to_string());
+            *count += 1;
+            table.clone()
+        } else if let Some(header) = line.strip_prefix('[') {
+            table = split_key(header.split(']').next().unwrap_or_default());
+            table.clone()
+        } else if let Some((name, _)) = line.split_once('=')
+            && !line.starts_with('#')
+        {
+            table.iter().cloned().chain(split_key(name)).collect()
+        } else {
+            continue;
+        };
+        if path.iter().map(String::as_str).eq(key.iter().copied()) {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+// ":line" of `key` for messages about it, if it can be found.
+fn at(content: &str, key: &str) -> String {
+    line_of(content, &key.split('.').collect::<Vec<_>>())
+        .map_or(String::new(), |line| format!(":{line}"))
+}
+
+// Reads a config file. Keys pino doesn't know and values it can't use come back as
+// warnings and are left out, a file that can't be read or parsed is an error.
+pub fn read(path: &Path) -> Result<(Config, Vec<String>), String> {
+    let name = path.display();
+    let content = fs::read_to_string(path).map_err(|e| format!("{name}: {e}"))?;
+    let mut unknown = Vec::new();
+    let mut deserializer = toml::Deserializer::new(&content);
+    let config: Result<Config, _> =
+        serde_ignored::deserialize(&mut deserializer, |key| unknown.push(key.to_string()));
+    let mut config = config.map_err(|e| format!("{name}: {e}"))?;
+
+    let mut warnings = Vec::new();
+    for key in unknown {
+        // Optional values show up as `?` in the path.
+        let key: Vec<&str> = key.split('.').filter(|part| *part != "?").collect();
+        let key = key.join(".");
+        warnings.push(format!("{name}{}: unknown key `{key}`", at(&content, &key)));
+    }
+    // Names can only be looked up once the files are merged, so this only checks how
+    // the colors are written.
+    let mut check = |key: String, value: &mut Option<String>| {
+        if let Some(Err(e)) = value.as_deref().map(colors::parse) {
+            warnings.push(format!(
+                "{name}{}: `{key}` is not a color: {e}",
+                at(&content, &key)
+            ));
+            *value = None;
+        }
+    };
+    for (key, value) in colors(&mut config) {
+        check(key, value);
+    }
+    for (key, value) in config.pywal.colors() {
+        check(key, value);
+    }
+    let mut i = 0;
+    config.rule.retain(|rule| {
+        let compiled = rule.compile();
+        if let Err(e) = &compiled {
+            let key = format!("rule.{i}");
+            warnings.push(format!(
+                "{name}{}: rule {i} is left out: {e}",
+                at(&content, &key)
+            ));
+        }
+        i += 1;
+        compiled.is_ok()
+    });
+    Ok((config, warnings))
+}
+
+// The system-wide config files, lowest priority first: $XDG_CONFIG_DIRS (/etc/xdg by
+// default) lists the most important directory first.
+pub fn system_files() -> Vec<PathBuf> {
+    let dirs = env::var("XDG_CONFIG_DIRS").unwrap_or("/etc/xdg".to_string());
+    dirs.split(':')
+        .filter(|dir| !dir.is_empty())
+        .rev()
+        .map(|dir| PathBuf::from(dir).join("pino").join("config.toml"))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+// The file next to the user config that applies to one session only.
+pub fn overlay(user: &Path, session: u8) -> PathBuf {
+    user.with_file_name(format!("session-{session}.toml"))
+}
+
+// The files that make up the config, lowest priority first: the system-wide ones,
+// `user` (always when it was asked for, otherwise if it exists) and the overlay of
+// the session next to it.
+pub fn layers(user: &Path, required: bool, session: u8) -> Vec<PathBuf> {
+    let mut layers = system_files();
+    if required || user.exists() {
+        layers.push(user.to_path_buf());
+    }
+    let overlay = overlay(user, session);
+    if overlay.exists() {
+        layers.push(overlay);
+    }
+    layers
+}
+
+// Lays `over` on top of `base` key by key: tables merge, `[[rule]]` tables add up and
+// anything else replaces what is below, arrays included.
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Table(base), Value::Table(over)) => {
+            for (key, value) in over {
+                match (base.get_mut(&key), value) {
+                    (Some(Value::Array(rules)), Value::Array(more)) if key == "rule" => {
+                        rules.extend(more)
+                    }
+                    (Some(below), value) => merge(below, value),
+                    (None, value) => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+// Reads `path` after the files it includes (relative to its directory, ~ for the
+// home directory) and merges them all into `merged`.
+fn layer(path: &Path, merged: &mut Value, files: &mut Vec<PathBuf>, problems: &mut Vec<String>) {
+    if files.iter().any(|file| file == path) {
+        problems.push(format!("{}: included more than once", path.display()));
+        return;
+    }
+    let (config, warnings) = match read(path) {
+        Ok(read) => read,
+        Err(e) => {
+            problems.push(format!("{e}, the file is left out"));
+            return;
+        }
+    };
+    files.push(path.to_path_buf());
+    problems.extend(warnings);
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for include in &config.include {
+        // An absolute path replaces `dir` when joined.
+        layer(&dir.join(home_path(include)), merged, files, problems);
+    }
+    match Value::try_from(&config) {
+        Ok(value) => merge(merged, value),
+        Err(e) => problems.push(format!("{}: {e}", path.display())),
+    }
+}
+
+// Reads the layers in order and merges them into one config, with the palette colors
+// looked up. Files that can't be read are left out. Also returns the files that were read and what is wrong with them.
+pub fn read_all(paths: &[PathBuf]) -> (Config, Vec<PathBuf>, Vec<String>) {
+    let mut merged = Value::Table(Default::default());
+    let (mut files, mut problems) = (Vec::new(), Vec::new());
+    for path in paths {
+        layer(path, &mut merged, &mut files, &mut problems);
+    }
+    let mut config = merged.try_into().unwrap_or_else(|e| {
+        problems.push(format!("Can't merge the config files: {e}"));
+        Config::default()
+    });
+    problems.extend(resolve_colors(&mut config));
+    (config, files, problems)
+}
+
+// Reads the layers and prints what is wrong with them. pino still starts, with the
+// built-in defaults for whatever couldn't be read. Also returns the files that were
+// read, includes among them.
+pub fn load(paths: &[PathBuf]) -> (Config, Vec<PathBuf>) {
+    let (config, files, problems) = read_all(paths);
+    for problem in problems {
+        eprintln!("Warning: {problem}");
+    }
+    (config, files)
+}
+
+// Values of one file that would quietly fall back to something else once pino runs.
+fn check_values(path: &Path, config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    let name = path.display();
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let mut problem = |key: &str, message: String| {
+        problems.push(format!("{name}{}: {message}", at(&content, key)));
+    };
+
+    let mut placements = vec![("screen.placement".to_string(), &config.screen.placement)];
+    for (i, rule) in config.rule.iter().enumerate() {
+        placements.push((format!("rule.{i}.placement"), &rule.placement));
+    }
+    for (key, placement) in placements {
+        if let Some(placement) = placement
+            && !ui::PLACEMENTS.contains(&placement.as_str())
+        {
+            problem(
+                &key,
+                format!(
+                    "`{key}` is not one of {}: {placement}",
+                    ui::PLACEMENTS.join(", ")
+                ),
+            );
+        }
+    }
+    if let Some(family) = &config.frame.font_family {
+        match ui::has_font(family) {
+            Some(true) => {}
+            Some(false) => problem(
+                "frame.font_family",
+                format!("no font is named `{family}` (see pino -f)"),
+            ),
+            None => eprintln!(
+                "Note: there is no display to list the fonts, so `{family}` isn't checked"
+            ),
+        }
+    }
+    if let Some(position) = &config.icon.position
+        && !["left", "right"].contains(&position.as_str())
+    {
+        problem(
+            "icon.position",
+            format!("`icon.position` is not left or right: {position}"),
+        );
+    }
+    if let Some(on_end) = &config.dnd.on_end
+        && !["summary", "discard"].contains(&on_end.as_str())
+    {
+        problem(
+            "dnd.on_end",
+            format!("`dnd.on_end` is not summary or discard: {on_end}"),
+        );
+    }
+    problems
+}
+
+// Everything wrong with the layers and the files they include: what `read` warns
+// about, values pino can't use and palette colors that can't be found. Also returns the
+// files that were read.
+pub fn check(paths: &[PathBuf]) -> (Vec<PathBuf>, Vec<String>) {
+    let (_, files, mut problems) = read_all(paths);
+    for file in &files {
+        if let Ok((layer, _)) = read(file) {
+            problems.extend(check_values(file, &layer));
+        }
+    }
+    (files, problems)
+}
+
+fn table<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::Table(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+// The keys of an `[urgency.*]` section.
+fn style_table(style: &ui::Style) -> Value {
+    table([
+        ("background_color", style.colors.0.as_str().into()),
+        ("border_color", style.colors.1.as_str().into()),
+        ("title_color", style.colors.2.as_str().into()),
+        ("message_color", style.colors.3.as_str().into()),
+        ("border_weight", style.border.0.into()),
+        ("border_radius", style.border.1.into()),
+        ("title_size", style.title.2.into()),
+        ("message_size", style.message.2.into()),
+        ("delay", (style.delay as i64).into()),
+        ("sound", style.sound.as_str().into()),
+    ])
+}
+
+// What pino runs with, in the shape of the config file, once the defaults and the
+// palette colors are filled in. The rules are the ones that compiled.
+pub fn dump(settings: &ui::Settings, rules: &[Rule]) -> Value {
+    let (styles, layout) = (&settings.styles, &settings.layout);
+    let style = &styles.base;
+    let rules = rules
+        .iter()
+        .filter_map(|rule| Value::try_from(rule).ok())
+        .collect();
+    table([
+        (
+            "screen",
+            table([
+                ("monitor", layout.monitor.into()),
+                ("placement", layout.placement.as_str().into()),
+                ("x", layout.offset.0.into()),
+                ("y", layout.offset.1.into()),
+                ("width", layout.size.0.into()),
+                ("height", layout.size.1.into()),
+                ("delay", (style.delay as i64).into()),
+                ("gap", layout.gap.into()),
+                ("max_visible", (layout.max_visible as i64).into()),
+                ("auto_height", layout.auto_height.into()),
+                ("max_height", layout.max_height.into()),
+            ]),
+        ),
+        (
+            "frame",
+            table([
+                ("fg_color", style.colors.0.as_str().into()),
+                ("font_family", style.font_family.as_str().into()),
+            ]),
+        ),
+        (
+            "border",
+            table([
+                ("weight", style.border.0.into()),
+                ("color", style.colors.1.as_str().into()),
+                ("radius", style.border.1.into()),
+            ]),
+        ),
+        (
+            "title",
+            table([
+                ("color", style.colors.2.as_str().into()),
+                ("font_size", style.title.2.into()),
+                ("x", style.title.0.into()),
+                ("y", style.title.1.into()),
+            ]),
+        ),
+        (
+            "message",
+            table([
+                ("color", style.colors.3.as_str().into()),
+                ("font_size", style.message.2.into()),
+                ("x", style.message.0.into()),
+                ("y", style.message.1.into()),
+                ("max_lines", (style.max_lines as i64).into()),
+            ]),
+        ),
+        (
+            "icon",
+            table([
+                ("size", style.icon.2.into()),
+                ("position", style.icon_position.as_str().into()),
+                ("x", style.icon.0.into()),
+                ("y", style.icon.1.into()),
+                ("theme", style.icon_theme.as_str().into()),
+            ]),
+        ),
+        (
+            "progress",
+            table([
+                ("color", style.progress_colors.0.as_str().into()),
+                ("background_color", style.progress_colors.1.as_str().into()),
+                ("height", style.progress.2.into()),
+                ("x", style.progress.0.into()),
+                ("y", style.progress.1.into()),
+                ("radius", style.progress.3.into()),
+            ]),
+        ),
+        (
+            "urgency",
+            table([
+                ("low", style_table(&styles.low)),
+                ("normal", style_table(&styles.normal)),
+                ("critical", style_table(&styles.critical)),
+            ]),
+        ),
+        (
+            "history",
+            table([("limit", (settings.history.limit() as i64).into())]),
+        ),
+        (
+            "dnd",
+            table([
+                ("bypass_critical", settings.dnd.bypass_critical.into()),
+                ("on_end", settings.dnd.on_end.as_str().into()),
+            ]),
+        ),
+        ("rule", Value::Array(rules)),
+        (
+            "optional",
+            table([
+                ("sound", settings.sound.into()),
+                ("sound_file", style.sound.as_str().into()),
+                ("reload_visible", settings.reload_visible.into()),
+            ]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for the files of one test.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pino-test-{}-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn finds_lines_of_keys() {
+        let content = "\
+# x = 1
+[screen]
+x = 10
+  y = 20
+
+[[rule]]
+app = \"a\"
+[[rule]]
+# title = \"no\"
+title = \"b\"
+[urgency.low]
+delay = 3
+[\"border\"]
+\"radius\" = 4
+";
+        assert_eq!(line_of(content, &["screen"]), Some(2));
+        assert_eq!(line_of(content, &["screen", "x"]), Some(3));
+        assert_eq!(line_of(content, &["screen", "y"]), Some(4));
+        assert_eq!(line_of(content, &["rule", "0", "app"]), Some(7));
+        assert_eq!(line_of(content, &["rule", "1"]), Some(8));
+        assert_eq!(line_of(content, &["rule", "1", "title"]), Some(10));
+        assert_eq!(line_of(content, &["urgency", "low", "delay"]), Some(12));
+        assert_eq!(line_of(content, &["border", "radius"]), Some(14));
+        assert_eq!(line_of(content, &["x"]), None);
+        assert_eq!(line_of(content, &["rule", "2"]), None);
+        assert_eq!(at(content, "screen.x"), ":3");
+        assert_eq!(at(content, "screen.monitor"), "");
+    }
+
+    #[test]
+    fn merges_layers_and_adds_up_rules() {
+        let dir = scratch("merge");
+        let system = write(
+            &dir,
+            "system.toml",
+            "[screen]\nx = 1\ny = 2\n[[rule]]\napp = \"a\"\n",
+        );
+        let user = write(
+            &dir,
+            "user.toml",
+            "[screen]\ny = 5\n[[rule]]\napp = \"b\"\n[[rule]]\napp = \"c\"\n",
+        );
+        let (config, files, problems) = read_all(&[system.clone(), user.clone()]);
+        assert_eq!(files, vec![system, user]);
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!((config.screen.x, config.screen.y), (Some(1), Some(5)));
+        let apps: Vec<_> = config.rule.iter().map(|rule| rule.app.as_deref()).collect();
+        assert_eq!(apps, vec![Some("a"), Some("b"), Some("c")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn includes_come_before_the_file() {
+        let dir = scratch("include");
+        write(&dir, "theme.toml", "[screen]\nx = 1\ny = 1\n");
+        let main = write(
+            &dir,
+            "main.toml",
+            "include = [\"theme.toml\"]\n[screen]\ny = 2\n",
+        );
+        let (config, files, problems) = read_all(std::slice::from_ref(&main));
+        assert_eq!(files, vec![main, dir.join("theme.toml")]);
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!((config.screen.x, config.screen.y), (Some(1), Some(2)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_cycles_stop() {
+        let dir = scratch("cycle");
+        let a = write(&dir, "a.toml", "include = [\"b.toml\"]\n[screen]\nx = 1\n");
+        let b = write(&dir, "b.toml", "include = [\"a.toml\"]\n[screen]\ny = 2\n");
+        let (config, files, problems) = read_all(std::slice::from_ref(&a));
+        assert_eq!(files, vec![a.clone(), b]);
+        assert_eq!(
+            problems,
+            vec![format!("{}: included more than once", a.display())]
+        );
+        assert_eq!((config.screen.x, config.screen.y), (Some(1), Some(2)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn warns_about_unknown_keys_with_their_line() {
+        let dir = scratch("unknown");
+        let path = write(&dir, "config.toml", "[screen]\nx = 1\nwidht = 300\n");
+        let (config, warnings) = read(&path).unwrap();
+        assert_eq!(config.screen.x, Some(1));
+        assert_eq!(
+            warnings,
+            vec![format!("{}:3: unknown key `screen.widht`", path.display())]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,423 @@
+use crate::{
+    protocol::{Action, CloseReason, Icon, ImageData, Notification, Reply, Urgency},
+    server::Command,
+};
+use dbus::{
+    Message,
+    arg::{PropMap, RefArg},
+    blocking::{
+        Connection,
+        stdintf::org_freedesktop_dbus::{Properties, RequestNameReply},
+    },
+    channel::Sender as _,
+};
+use dbus_crossroads::{Context, Crossroads, IfaceToken, MethodErr};
+use fltk::app;
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+const NOTIFICATIONS_INTERFACE: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+const MEDIA_PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+const MEDIA_PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+const MEDIA_PLAYERS: &[&str] = &["spotify", "mpv", "vlc", "chromium", "firefox", "brave"];
+
+// Data of the /org/freedesktop/Notifications object. Every notification sent over
+// D-Bus is waited on through `reply`, so its actions and closes come back on `replies`
+// and are turned into signals.
+struct Server {
+    commands: Sender<Command>,
+    reply: Sender<Reply>,
+    replies: Receiver<Reply>,
+    signals: Vec<Message>,
+}
+
+impl Server {
+    fn send(&self, command: Command) -> Result<(), MethodErr> {
+        self.commands
+            .send(command)
+            .map_err(|_| MethodErr::failed("pino is shutting down"))?;
+        app::awake();
+        Ok(())
+    }
+
+    fn forward(&mut self, reply: Reply) {
+        let signal = |name| {
+            Message::new_signal(NOTIFICATIONS_PATH, NOTIFICATIONS_INTERFACE, name)
+                .expect("valid signal")
+        };
+        match reply {
+            Reply::Action { id, key } => {
+                self.signals.push(signal("ActionInvoked").append2(id, key))
+            }
+            // A notification replaced over D-Bus keeps its ID, so it did not close.
+            Reply::Closed { id, reason } if reason != CloseReason::Replaced => self
+                .signals
+                .push(signal("NotificationClosed").append2(id, reason as u32)),
+            _ => {}
+        }
+    }
+
+    fn notify(
+        &mut self,
+        notification: Notification,
+        replaces: Option<u32>,
+    ) -> Result<u32, MethodErr> {
+        self.send(Command::Notify {
+            notification,
+            replaces,
+            wait: true,
+            reply: self.reply.clone(),
+        })?;
+        // Replies arriving before ours belong to notifications shown earlier.
+        loop {
+            match self.replies.recv() {
+                Ok(Reply::Shown { id }) => return Ok(id),
+                Ok(Reply::Error { message }) => return Err(MethodErr::failed(&message)),
+                Ok(reply) => self.forward(reply),
+                Err(_) => return Err(MethodErr::failed("pino is shutting down")),
+            }
+        }
+    }
+
+    fn close(&self, id: u32) -> Result<(), MethodErr> {
+        let (reply, replies) = mpsc::channel();
+        self.send(Command::Close { id, reply })?;
+        match replies.recv() {
+            Ok(Reply::Error { message }) => Err(MethodErr::failed(&message)),
+            Ok(_) => Ok(()),
+            Err(_) => Err(MethodErr::failed("pino is shutting down")),
+        }
+    }
+}
+
+// Reads an `image-data` (iiibiiay) hint.
+fn image_data(value: &dyn RefArg) -> Option<ImageData> {
+    let mut fields = value.as_iter()?;
+    let mut int = || fields.next().and_then(|v| v.as_i64()).map(|v| v as i32);
+    let (width, height, rowstride) = (int()?, int()?, int()?);
+    let has_alpha = int()? != 0;
+    let (bits_per_sample, channels) = (int()?, int()?);
+    let data = fields
+        .next()?
+        .as_iter()?
+        .filter_map(|byte| byte.as_u64().map(|b| b as u8))
+        .collect();
+    Some(ImageData {
+        width,
+        height,
+        rowstride,
+        has_alpha,
+        bits_per_sample,
+        channels,
+        data,
+    })
+}
+
+// Picks the icon the way the spec orders them: image-data, image-path, app_icon.
+fn icon(app_icon: String, hints: &PropMap) -> Option<Icon> {
+    let data = ["image-data", "image_data", "icon_data"]
+        .iter()
+        .find_map(|key| hints.get(*key))
+        .and_then(|data| image_data(&data.0));
+    if let Some(data) = data {
+        return Some(Icon::Data(data));
+    }
+    ["image-path", "image_path"]
+        .iter()
+        .find_map(|key| hints.get(*key).and_then(|v| v.as_str()))
+        .map(String::from)
+        .or(Some(app_icon))
+        .filter(|icon| !icon.is_empty())
+        .map(Icon::Name)
+}
+
+// suppress-sound wins over sound-file, which wins over sound-name.
+fn sound(hints: &PropMap) -> Option<String> {
+    let suppress = hints
+        .get("suppress-sound")
+        .and_then(|v| v.as_u64())
+        .is_some_and(|v| v != 0);
+    if suppress {
+        return Some(String::new());
+    }
+    ["sound-file", "sound-name"]
+        .iter()
+        .find_map(|key| hints.get(*key).and_then(|v| v.as_str()))
+        .map(String::from)
+}
+
+fn notification(
+    app_name: String,
+    app_icon: String,
+    summary: String,
+    body: String,
+    actions: Vec<String>,
+    hints: PropMap,
+    expire_timeout: i32,
+) -> Notification {
+    let urgency = match hints.get("urgency").and_then(|v| v.as_u64()) {
+        Some(0) => Urgency::Low,
+        Some(2) => Urgency::Critical,
+        _ => Urgency::Normal,
+    };
+    // -1 leaves the delay to the urgency, 0 never expires and pino counts in whole seconds.
+    let delay = match expire_timeout {
+        0 => Some(0),
+        ms if ms > 0 => Some((ms as u64).div_ceil(1000)),
+        _ => None,
+    };
+    Notification {
+        title: summary,
+        message: body,
+        markup: true,
+        delay,
+        urgency,
+        icon: icon(app_icon, &hints),
+        progress: hints
+            .get("value")
+            .and_then(|v| v.as_i64())
+            .map(|v| v.clamp(0, 100) as u8),
+        // The spec sends actions as a flat list of key, label pairs.
+        actions: actions
+            .chunks_exact(2)
+            .map(|pair| Action {
+                key: pair[0].clone(),
+                label: pair[1].clone(),
+            })
+            .collect(),
+        app_name: Some(app_name),
+        sound: sound(&hints),
+    }
+}
+
+fn register(cr: &mut Crossroads) -> IfaceToken<Server> {
+    cr.register(NOTIFICATIONS_INTERFACE, |b| {
+        b.signal::<(u32, String), _>("ActionInvoked", ("id", "action_key"));
+        b.signal::<(u32, u32), _>("NotificationClosed", ("id", "reason"));
+
+        b.method(
+            "Notify",
+            (
+                "app_name",
+                "replaces_id",
+                "app_icon",
+                "summary",
+                "body",
+                "actions",
+                "hints",
+                "expire_timeout",
+            ),
+            ("id",),
+            |_: &mut Context,
+             server: &mut Server,
+             (app_name, replaces_id, app_icon, summary, body, actions, hints, expire_timeout): (
+                String,
+                u32,
+                String,
+                String,
+                String,
+                Vec<String>,
+                PropMap,
+                i32,
+            )| {
+                let notification = notification(
+                    app_name,
+                    app_icon,
+                    summary,
+                    body,
+                    actions,
+                    hints,
+                    expire_timeout,
+                );
+                let replaces = (replaces_id != 0).then_some(replaces_id);
+                Ok((server.notify(notification, replaces)?,))
+            },
+        );
+
+        b.method(
+            "GetCapabilities",
+            (),
+            ("capabilities",),
+            |_: &mut Context, _: &mut Server, _: ()| {
+                let mut capabilities = vec![
+                    "actions",
+                    "body",
+                    "body-hyperlinks",
+                    "body-markup",
+                    "icon-static",
+                ];
+                if cfg!(feature = "sound") {
+                    capabilities.push("sound");
+                }
+                Ok((capabilities,))
+            },
+        );
+
+        b.method(
+            "CloseNotification",
+            ("id",),
+            (),
+            |_: &mut Context, server: &mut Server, (id,): (u32,)| server.close(id),
+        );
+
+        b.method(
+            "GetServerInformation",
+            (),
+            ("name", "vendor", "version", "spec_version"),
+            |_: &mut Context, _: &mut Server, _: ()| {
+                Ok(("pino", "Pixel2175", env!("CARGO_PKG_VERSION"), "1.2"))
+            },
+        );
+    })
+}
+
+#[derive(PartialEq)]
+struct MediaInfo {
+    title: String,
+    artist: String,
+    status: String,
+}
+
+impl MediaInfo {
+    fn to_notification(&self, player: &str) -> Notification {
+        let body = if !self.artist.is_empty() && !self.title.is_empty() {
+            format!("{} - {}", self.artist, self.title)
+        } else if !self.title.is_empty() {
+            self.title.clone()
+        } else {
+            "Now Playing".to_string()
+        };
+        Notification {
+            title: format!("{} - {}", player, self.status),
+            message: body,
+            markup: false,
+            delay: None,
+            urgency: Urgency::Low,
+            icon: None,
+            progress: None,
+            actions: vec![],
+            app_name: Some(player.to_string()),
+            sound: None,
+        }
+    }
+}
+
+fn get_media_info(conn: &Connection, player: &str) -> Option<MediaInfo> {
+    let proxy = conn.with_proxy(
+        format!("org.mpris.MediaPlayer2.{}", player),
+        MEDIA_PLAYER_PATH,
+        Duration::from_millis(500),
+    );
+    let metadata: PropMap = proxy.get(MEDIA_PLAYER_INTERFACE, "Metadata").ok()?;
+    let status: Option<String> = proxy.get(MEDIA_PLAYER_INTERFACE, "PlaybackStatus").ok();
+
+    let title = metadata
+        .get("xesam:title")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let artist = metadata
+        .get("xesam:artist")
+        .and_then(|v| v.as_iter())
+        .and_then(|mut iter| iter.next())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    Some(MediaInfo {
+        title: title.to_string(),
+        artist: artist.to_string(),
+        status: status.unwrap_or_default(),
+    })
+}
+
+// Polls the MPRIS players and shows what they play whenever it changes, updating the
+// player's previous notification in place.
+fn monitor_media_players(commands: Sender<Command>) {
+    let Ok(conn) = Connection::new_session() else {
+        return;
+    };
+    let mut playing: HashMap<&str, (MediaInfo, u32)> = HashMap::new();
+    loop {
+        for player in MEDIA_PLAYERS {
+            let Some(info) = get_media_info(&conn, player) else {
+                continue;
+            };
+            if playing.get(player).is_some_and(|(last, _)| *last == info) {
+                continue;
+            }
+            let (reply, replies) = mpsc::channel();
+            let command = Command::Notify {
+                notification: info.to_notification(player),
+                replaces: playing.get(player).map(|(_, id)| *id),
+                wait: false,
+                reply,
+            };
+            if commands.send(command).is_err() {
+                return;
+            }
+            app::awake();
+            if let Ok(Reply::Shown { id }) = replies.recv() {
+                playing.insert(player, (info, id));
+            }
+        }
+        thread::sleep(Duration::from_secs(5));
+    }
+}
+
+fn run(commands: Sender<Command>) -> Result<(), Box<dyn Error>> {
+    let conn = Connection::new_session()?;
+    match conn.request_name(NOTIFICATIONS_INTERFACE, false, true, true)? {
+        RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner => {}
+        _ => return Err(format!("{NOTIFICATIONS_INTERFACE} is owned by another daemon").into()),
+    }
+
+    let media = commands.clone();
+    thread::spawn(move || monitor_media_players(media));
+
+    let (reply, replies) = mpsc::channel();
+    let mut cr = Crossroads::new();
+    let token = register(&mut cr);
+    cr.insert(
+        NOTIFICATIONS_PATH,
+        &[token],
+        Server {
+            commands,
+            reply,
+            replies,
+            signals: Vec::new(),
+        },
+    );
+
+    let path = NOTIFICATIONS_PATH.into();
+    loop {
+        if let Some(message) = conn
+            .channel()
+            .blocking_pop_message(Duration::from_millis(100))?
+        {
+            // Anything that isn't a method call (e.g. NameAcquired) is ignored.
+            let _ = cr.handle_message(message, &conn);
+        }
+        let server: &mut Server = cr.data_mut(&path).expect("object is registered");
+        while let Ok(reply) = server.replies.try_recv() {
+            server.forward(reply);
+        }
+        for signal in server.signals.drain(..) {
+            let _ = conn.send(signal);
+        }
+    }
+}
+
+// Serves org.freedesktop.Notifications on the session bus, feeding the notifications
+// into the same queue as the session socket.
+pub fn serve(commands: Sender<Command>) {
+    thread::spawn(move || {
+        if let Err(e) = run(commands) {
+            eprintln!("D-Bus server stopped: {e}");
+        }
+    });
+}
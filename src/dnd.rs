@@ -0,0 +1,33 @@
+use crate::history;
+use std::{fs, path::PathBuf};
+
+// Do-not-disturb settings of a session. While it is on, notifications are held back
+// and, when it ends, shown as one summary or dropped (they stay in the history).
+pub struct Dnd {
+    pub session: u8,
+    pub enabled: bool,
+    pub bypass_critical: bool,
+    // "summary" or "discard"
+    pub on_end: String,
+}
+
+fn path(session: u8) -> PathBuf {
+    history::state_dir().join(format!("dnd-{session}"))
+}
+
+// Whether do-not-disturb was left on for the session.
+pub fn load(session: u8) -> bool {
+    fs::read_to_string(path(session)).is_ok_and(|state| state.trim() == "on")
+}
+
+pub fn save(session: u8, enabled: bool) {
+    let path = path(session);
+    let state = if enabled { "on\n" } else { "off\n" };
+    let saved = fs::create_dir_all(history::state_dir()).and_then(|_| fs::write(&path, state));
+    if let Err(e) = saved {
+        eprintln!(
+            "Can't save the do-not-disturb state to {}: {e}",
+            path.display()
+        );
+    }
+}
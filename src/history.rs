@@ -0,0 +1,334 @@
+use crate::protocol::{CloseReason, Icon, Notification};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    // seconds since the Unix epoch
+    pub time: i64,
+    pub session: u8,
+    pub reason: CloseReason,
+    pub notification: Notification,
+}
+
+// Just the ID of an entry, to number the next one.
+#[derive(Deserialize)]
+struct Id {
+    id: u64,
+}
+
+// Every notification the server closes is appended to the history file as one line
+// of JSON, and only the last `limit` of them are kept (0 turns the history off).
+pub struct History {
+    session: u8,
+    limit: usize,
+}
+
+// $XDG_STATE_HOME/pino, where pino keeps what has to outlive the server.
+pub fn state_dir() -> PathBuf {
+    let state = env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(env::var("HOME").unwrap_or_default())
+                .join(".local")
+                .join("state")
+        });
+    state.join("pino")
+}
+
+pub fn path() -> PathBuf {
+    state_dir().join("history.jsonl")
+}
+
+// The entries from oldest to newest. Lines that can't be read are skipped.
+pub fn load() -> io::Result<Vec<Entry>> {
+    let content = match fs::read_to_string(path()) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64)
+}
+
+impl History {
+    pub fn new(session: u8, limit: usize) -> History {
+        History { session, limit }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn record(&self, notification: &Notification, reason: CloseReason) {
+        if self.limit == 0 {
+            return;
+        }
+        if let Err(e) = self.append(&path(), notification, reason) {
+            eprintln!("Can't write the history to {}: {e}", path().display());
+        }
+    }
+
+    fn append(
+        &self,
+        path: &Path,
+        notification: &Notification,
+        reason: CloseReason,
+    ) -> io::Result<()> {
+        fs::create_dir_all(path.parent().unwrap())?;
+        // The servers of every session write to the same file, so they take turns.
+        let lock = fs::File::create(path.with_extension("jsonl.lock"))?;
+        lock.lock()?;
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let lines: Vec<&str> = content.lines().collect();
+        // Lines that can't be read don't count, but the IDs of all the others do.
+        let last = lines
+            .iter()
+            .filter_map(|line| serde_json::from_str::<Id>(line).ok())
+            .map(|entry| entry.id)
+            .max();
+
+        // Raw image data would bloat the file, so it isn't kept.
+        let mut notification = notification.clone();
+        if let Some(Icon::Data(_)) = notification.icon {
+            notification.icon = None;
+        }
+        let entry = Entry {
+            id: last.map_or(1, |last| last + 1),
+            time: now(),
+            session: self.session,
+            reason,
+            notification,
+        };
+        let line = serde_json::to_string(&entry)?;
+
+        if lines.len() < self.limit {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            return writeln!(file, "{line}");
+        }
+        // Over the limit: write the newest entries to a new file and swap it in.
+        let keep = &lines[lines.len() + 1 - self.limit..];
+        let tmp = path.with_extension(format!("jsonl.{}.tmp", process::id()));
+        let mut file = fs::File::create(&tmp)?;
+        for kept in keep {
+            writeln!(file, "{kept}")?;
+        }
+        writeln!(file, "{line}")?;
+        fs::rename(tmp, path)
+    }
+}
+
+// Accepts a duration back from now (30s, 10m, 2h, 1d), a date (2024-05-01) or a local
+// date and time (2024-05-01 18:30, 2024-05-01T18:30:00), and returns the Unix time.
+pub fn parse_since(since: &str) -> Result<i64, String> {
+    let since = since.trim();
+    if let Some(unit) = since.chars().last().filter(char::is_ascii_alphabetic) {
+        let amount: i64 = since[..since.len() - 1]
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid duration `{since}`"))?;
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => {
+                return Err(format!(
+                    "unknown unit `{unit}` in `{since}` (use s, m, h, d or w)"
+                ));
+            }
+        };
+        return Ok(now() - amount * seconds);
+    }
+
+    let datetime = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(since, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(since, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+    })
+    .ok_or_else(|| format!("can't read the time `{since}`"))?;
+    Local
+        .from_local_datetime(&datetime)
+        .earliest()
+        .map(|time| time.timestamp())
+        .ok_or_else(|| format!("`{since}` doesn't exist in the local time zone"))
+}
+
+pub fn print(entries: &[Entry], json: bool) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    if json {
+        serde_json::to_writer_pretty(&mut out, entries)?;
+        return writeln!(out);
+    }
+    for entry in entries {
+        let time = DateTime::from_timestamp(entry.time, 0)
+            .map(|time| {
+                time.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        let notification = &entry.notification;
+        let app = notification.app_name.as_deref().unwrap_or("pino");
+        writeln!(
+            out,
+            "{:>5}  {time}  session {}  {app}  {}  {}",
+            entry.id, entry.session, notification.urgency, entry.reason
+        )?;
+        writeln!(out, "       {}", notification.title)?;
+        let message = crate::markup::strip(&notification.message);
+        for line in message.lines().filter(|line| !line.trim().is_empty()) {
+            writeln!(out, "       {line}")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Urgency;
+
+    fn notification(title: &str) -> Notification {
+        Notification {
+            title: title.to_string(),
+            message: String::new(),
+            markup: false,
+            delay: None,
+            urgency: Urgency::Normal,
+            icon: None,
+            progress: None,
+            actions: Vec::new(),
+            app_name: None,
+            sound: None,
+        }
+    }
+
+    // A history file of its own for one test.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pino-history-{}-{test}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("history.jsonl")
+    }
+
+    fn entries(path: &Path) -> Vec<(u64, String)> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let entry: Entry = serde_json::from_str(line).unwrap();
+                (entry.id, entry.notification.title)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_last_entries() {
+        let path = scratch("limit");
+        let history = History::new(0, 3);
+        for title in ["a", "b", "c", "d", "e"] {
+            history
+                .append(&path, &notification(title), CloseReason::Expired)
+                .unwrap();
+        }
+        let kept = vec![
+            (3, "c".to_string()),
+            (4, "d".to_string()),
+            (5, "e".to_string()),
+        ];
+        assert_eq!(entries(&path), kept);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn numbers_after_the_largest_readable_id() {
+        let path = scratch("ids");
+        let history = History::new(0, 10);
+        history
+            .append(&path, &notification("a"), CloseReason::Closed)
+            .unwrap();
+        let mut content = fs::read_to_string(&path)
+            .unwrap()
+            .replace("\"id\":1", "\"id\":7");
+        content.push_str("{\"id\":9, cut off\n");
+        fs::write(&path, content).unwrap();
+
+        history
+            .append(&path, &notification("b"), CloseReason::Closed)
+            .unwrap();
+        let last = fs::read_to_string(&path).unwrap();
+        let last: Entry = serde_json::from_str(last.lines().last().unwrap()).unwrap();
+        assert_eq!(last.id, 8);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn parses_durations() {
+        let close = |since: &str, seconds: i64| {
+            let back = now() - parse_since(since).unwrap();
+            assert!((seconds..=seconds + 5).contains(&back), "{since}: {back}");
+        };
+        close("30s", 30);
+        close("10m", 600);
+        close(" 2h ", 2 * 60 * 60);
+        close("1d", 24 * 60 * 60);
+        close("1w", 7 * 24 * 60 * 60);
+        assert!(parse_since("5y").is_err());
+        assert!(parse_since("h").is_err());
+        assert!(parse_since("-").is_err());
+    }
+
+    #[test]
+    fn parses_local_times() {
+        let local = |y, m, d, h, min| {
+            Local
+                .with_ymd_and_hms(y, m, d, h, min, 0)
+                .earliest()
+                .unwrap()
+                .timestamp()
+        };
+        assert_eq!(parse_since("2024-05-01"), Ok(local(2024, 5, 1, 0, 0)));
+        assert_eq!(
+            parse_since("2024-05-01 18:30"),
+            Ok(local(2024, 5, 1, 18, 30))
+        );
+        assert_eq!(
+            parse_since("2024-05-01T18:30:00"),
+            Ok(local(2024, 5, 1, 18, 30))
+        );
+        assert!(parse_since("2024-13-01").is_err());
+        assert!(parse_since("yesterday").is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    protocol::{Icon, ImageData},
+    xdg,
+};
+use fltk::{
+    enums::ColorDepth,
+    frame::Frame,
+    image::{RgbImage, SharedImage},
+    prelude::*,
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+// Base directories of the freedesktop icon theme spec, in lookup order.
+fn icon_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(home) = env::var("HOME") {
+        dirs.push(PathBuf::from(home).join(".icons"));
+    }
+    dirs.extend(xdg::data_dirs().into_iter().map(|dir| dir.join("icons")));
+    dirs
+}
+
+fn inherits(theme: &Path) -> Vec<String> {
+    fs::read_to_string(theme.join("index.theme"))
+        .ok()
+        .and_then(|index| {
+            index
+                .lines()
+                .find_map(|line| line.strip_prefix("Inherits="))
+                .map(|parents| parents.split(',').map(|p| p.trim().to_string()).collect())
+        })
+        .unwrap_or_default()
+}
+
+// Themes lay icons out as <size>/<context>/<name> or <context>/<size>/<name>, so look
+// two levels deep, starting with the requested size and the scalable icons.
+fn find_in_theme(theme: &Path, name: &str, size: i32) -> Option<PathBuf> {
+    let exact = format!("{size}x{size}");
+    let mut dirs: Vec<PathBuf> = fs::read_dir(theme)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort_by_key(|dir| match dir.file_name().and_then(|n| n.to_str()) {
+        Some(n) if n == exact => 0,
+        Some("scalable") => 1,
+        _ => 2,
+    });
+
+    for dir in dirs {
+        for sub in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            for ext in EXTENSIONS {
+                let path = sub.path().join(format!("{name}.{ext}"));
+                if path.exists() {
+                    return Some(path);
+                }
+            }
+        }
+    }
+    None
+}
+
+// Turns a file path, file:// URI or icon theme name into the file to load.
+pub fn resolve(name: &str, theme: &str, size: i32) -> Option<PathBuf> {
+    if let Some(path) = xdg::file(name) {
+        return path.exists().then(|| path.to_path_buf());
+    }
+
+    // The configured theme, the themes it inherits from and finally hicolor.
+    let dirs = icon_dirs();
+    let mut themes = vec![theme.to_string()];
+    let mut i = 0;
+    while let Some(current) = themes.get(i).cloned() {
+        for dir in &dirs {
+            let path = dir.join(&current);
+            if !path.is_dir() {
+                continue;
+            }
+            if let Some(icon) = find_in_theme(&path, name, size) {
+                return Some(icon);
+            }
+            for parent in inherits(&path) {
+                if !themes.contains(&parent) {
+                    themes.push(parent);
+                }
+            }
+        }
+        i += 1;
+        if i == themes.len() && !themes.iter().any(|t| t == "hicolor") {
+            themes.push("hicolor".to_string());
+        }
+    }
+
+    EXTENSIONS
+        .iter()
+        .map(|ext| PathBuf::from("/usr/share/pixmaps").join(format!("{name}.{ext}")))
+        .find(|path| path.exists())
+}
+
+// Repacks a freedesktop image-data buffer (rows padded to `rowstride`) into the
+// tightly packed RGB/RGBA layout FLTK expects.
+fn rgb_image(data: &ImageData) -> Option<RgbImage> {
+    if data.bits_per_sample != 8 || data.channels != 3 + data.has_alpha as i32 {
+        return None;
+    }
+    // The sizes come from whoever sent the notification, so they have to fit the
+    // buffer before anything is allocated.
+    if data.width <= 0 || data.height <= 0 {
+        return None;
+    }
+    let (width, height) = (data.width as usize, data.height as usize);
+    let rowstride = usize::try_from(data.rowstride).ok()?;
+    let row = width.checked_mul(data.channels as usize)?;
+    let end = rowstride.checked_mul(height - 1)?.checked_add(row)?;
+    if rowstride < row || end > data.data.len() {
+        return None;
+    }
+    let mut pixels = Vec::with_capacity(row * height);
+    for y in 0..height {
+        let start = y * rowstride;
+        pixels.extend_from_slice(&data.data[start..start + row]);
+    }
+    let depth = if data.has_alpha {
+        ColorDepth::Rgba8
+    } else {
+        ColorDepth::Rgb8
+    };
+    RgbImage::new(&pixels, data.width, data.height, depth).ok()
+}
+
+// Puts the icon on `frame`, scaled to fit in `size`x`size`.
+pub fn show(frame: &mut Frame, icon: &Icon, theme: &str, size: i32) -> bool {
+    match icon {
+        Icon::Name(name) => {
+            let Some(mut image) =
+                resolve(name, theme, size).and_then(|p| SharedImage::load(p).ok())
+            else {
+                return false;
+            };
+            image.scale(size, size, true, true);
+            frame.set_image(Some(image));
+        }
+        Icon::Data(data) => {
+            let Some(mut image) = rgb_image(data) else {
+                return false;
+            };
+            image.scale(size, size, true, true);
+            frame.set_image(Some(image));
+        }
+    }
+    frame.redraw();
+    true
+}
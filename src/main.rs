@@ -1,11 +1,24 @@
 use argh::FromArgs;
-use fltk::app::screen_xywh;
-use serde::Deserialize;
-use std::{fs, io::Write, os::unix::net::UnixStream};
+use std::{os::unix::net::UnixStream, path::PathBuf, process, sync::mpsc, thread};
 
 mod colors;
 mod config;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod dnd;
+mod history;
+mod icon;
+mod markup;
+mod protocol;
+mod rules;
+mod server;
+mod sound;
 mod ui;
+mod watch;
+mod xdg;
+
+// Exit code of every failure, kept apart from the close reasons (1-4) that --wait exits with.
+const FAILURE: i32 = 5;
 
 #[derive(FromArgs)]
 #[argh(
@@ -34,6 +47,12 @@ struct Arg {
     )]
     delay: Option<u64>,
 
+    #[argh(
+        switch,
+        description = "render <b>, <i>, <u>, <a href> and <img alt> in the message"
+    )]
+    markup: bool,
+
     #[argh(
         option,
         short = 's',
@@ -53,65 +72,369 @@ struct Arg {
 
     #[argh(switch, short = 'v', description = "set a custom configuration file")]
     version: bool,
+
+    #[argh(
+        switch,
+        description = "keep running in the background and show notifications sent to the session"
+    )]
+    daemon: bool,
+
+    #[argh(switch, description = "stop the daemon running on the session")]
+    quit: bool,
+
+    #[argh(
+        switch,
+        description = "make the running instance read its config files again"
+    )]
+    reload: bool,
+
+    #[argh(
+        switch,
+        short = 'w',
+        description = "wait until the notification closes, print why and exit with its reason code"
+    )]
+    wait: bool,
+
+    #[argh(
+        option,
+        description = "update the notification with this ID in place instead of showing a new one"
+    )]
+    replace_id: Option<u32>,
+
+    #[argh(
+        switch,
+        description = "print the ID of the notification once it is shown"
+    )]
+    print_id: bool,
+
+    #[argh(
+        option,
+        short = 'u',
+        description = "set the urgency: low, normal or critical (default = normal)"
+    )]
+    urgency: Option<protocol::Urgency>,
+
+    #[argh(
+        option,
+        short = 'i',
+        description = "show an icon: an image file (png, svg, xpm) or an icon theme name"
+    )]
+    icon: Option<String>,
+
+    #[argh(
+        option,
+        short = 'p',
+        description = "show a progress bar filled to this value (0-100)"
+    )]
+    progress: Option<u8>,
+
+    #[argh(
+        option,
+        short = 'a',
+        description = "add a button as key=Label (repeatable), --wait prints the key of the one clicked"
+    )]
+    action: Vec<String>,
+
+    #[argh(
+        option,
+        description = "play this sound file or sound theme name instead of the configured one"
+    )]
+    sound: Option<String>,
+
+    #[argh(option, description = "close the notification with this ID")]
+    close: Option<u32>,
+
+    #[argh(switch, description = "close every notification of the session")]
+    close_all: bool,
+
+    #[argh(option, description = "close every notification of another session")]
+    close_session: Option<u8>,
+
+    #[argh(
+        option,
+        description = "do not disturb: on, off, toggle or status (notifications are held back while on)"
+    )]
+    dnd: Option<protocol::DndMode>,
+
+    #[argh(subcommand)]
+    command: Option<Subcommand>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Config {
-    screen: Screen,
-    frame: Frame,
-    border: Border,
-    title: Title,
-    message: Message,
-    pywal: Pywal,
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Subcommand {
+    History(HistoryArgs),
+    Config(ConfigArgs),
 }
 
-#[derive(Debug, Deserialize)]
-struct Screen {
-    monitor: Option<i32>,
-    placement: Option<String>,
-    x: Option<i32>,
-    y: Option<i32>,
-    width: Option<i32>,
-    height: Option<i32>,
-    delay: Option<u64>,
+#[derive(FromArgs)]
+#[argh(
+    subcommand,
+    name = "config",
+    description = "check or print the configuration"
+)]
+struct ConfigArgs {
+    #[argh(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum ConfigCommand {
+    Check(CheckArgs),
+    Dump(DumpArgs),
+}
+
+#[derive(FromArgs)]
+#[argh(
+    subcommand,
+    name = "check",
+    description = "report what is wrong with a config file (default: the one in use)"
+)]
+struct CheckArgs {
+    #[argh(positional)]
+    path: Option<String>,
+}
+
+#[derive(FromArgs)]
+#[argh(
+    subcommand,
+    name = "dump",
+    description = "print the configuration pino runs with, defaults and palette colors included"
+)]
+struct DumpArgs {}
+
+#[derive(FromArgs)]
+#[argh(
+    subcommand,
+    name = "history",
+    description = "list the notifications shown before, or show one of them again"
+)]
+struct HistoryArgs {
+    #[argh(option, description = "only list the last N notifications")]
+    last: Option<usize>,
+
+    #[argh(
+        option,
+        description = "only list notifications since a time: 30m, 2h, 1d, 2024-05-01 or \"2024-05-01 18:30\""
+    )]
+    since: Option<String>,
+
+    #[argh(switch, description = "print the entries as JSON")]
+    json: bool,
+
+    #[argh(
+        option,
+        description = "show the notification with this history ID again"
+    )]
+    replay: Option<u64>,
 }
-#[derive(Debug, Deserialize)]
-struct Frame {
-    fg_color: Option<String>,
-    font_family: Option<String>,
+
+// Layers an `[urgency.*]` section over the base style.
+fn urgency_style(base: &ui::Style, over: Option<&config::StyleOverride>, delay: u64) -> ui::Style {
+    let mut style = base.clone();
+    style.delay = delay;
+    if let Some(over) = over {
+        over.apply(&mut style);
+    }
+    style
 }
-#[derive(Debug, Deserialize)]
-struct Border {
-    weight: Option<i32>,
-    color: Option<String>,
-    radius: Option<i32>,
+
+// Turns the config into what the server runs with, filling in the defaults.
+fn settings(config: &config::Config, session: u8) -> ui::Settings {
+    let colors = (
+        config
+            .frame
+            .fg_color
+            .clone()
+            .unwrap_or("#000000".to_string()),
+        config.border.color.clone().unwrap_or("#62777d".to_string()),
+        config.title.color.clone().unwrap_or("#b8b8b8".to_string()),
+        config
+            .message
+            .color
+            .clone()
+            .unwrap_or("#501701".to_string()),
+    );
+
+    let (icon, progress) = (&config.icon, &config.progress);
+    let progress_colors = (
+        progress.color.clone().unwrap_or_else(|| colors.2.clone()),
+        progress
+            .background_color
+            .clone()
+            .unwrap_or_else(|| colors.1.clone()),
+    );
+    let style = ui::Style {
+        font_family: config
+            .frame
+            .font_family
+            .clone()
+            .unwrap_or("Monospace".to_string()),
+        border: (
+            config.border.weight.unwrap_or(2),
+            config.border.radius.unwrap_or(10),
+        ),
+        title: (
+            config.title.x.unwrap_or(5),
+            config.title.y.unwrap_or(0),
+            config.title.font_size.unwrap_or(17),
+        ),
+        message: (
+            config.message.x.unwrap_or(10),
+            config.message.y.unwrap_or(19),
+            config.message.font_size.unwrap_or(13),
+        ),
+        max_lines: config.message.max_lines.unwrap_or(0),
+        colors,
+        delay: config.screen.delay.unwrap_or(5),
+        icon: (
+            icon.x.unwrap_or(10),
+            icon.y.unwrap_or(0),
+            icon.size.unwrap_or(48),
+        ),
+        icon_position: icon.position.clone().unwrap_or("left".to_string()),
+        icon_theme: icon.theme.clone().unwrap_or("hicolor".to_string()),
+        progress: (
+            progress.x.unwrap_or(10),
+            progress.y.unwrap_or(-8),
+            progress.height.unwrap_or(6),
+            progress.radius.unwrap_or(3),
+        ),
+        progress_colors,
+        sound: config
+            .optional
+            .sound_file
+            .clone()
+            .unwrap_or("message-new-instant".to_string()),
+    };
+    let urgency = &config.urgency;
+    let styles = ui::Styles {
+        low: urgency_style(&style, urgency.low.as_ref(), style.delay),
+        normal: urgency_style(&style, urgency.normal.as_ref(), style.delay),
+        critical: urgency_style(&style, urgency.critical.as_ref(), 0),
+        base: style,
+    };
+
+    // Rules that don't compile were already reported and left out by config::read.
+    let rules = config
+        .rule
+        .iter()
+        .filter_map(|rule| rule.compile().ok())
+        .collect();
+
+    let screen = &config.screen;
+    ui::Settings {
+        styles,
+        layout: ui::Layout {
+            monitor: screen.monitor.unwrap_or(0),
+            placement: screen.placement.clone().unwrap_or("top_center".to_string()),
+            offset: (screen.x.unwrap_or(25), screen.y.unwrap_or(55)),
+            size: (screen.width.unwrap_or(400), screen.height.unwrap_or(60)),
+            auto_height: screen.auto_height.unwrap_or(false),
+            max_height: screen.max_height.unwrap_or(300),
+            gap: screen.gap.unwrap_or(10),
+            max_visible: screen.max_visible.unwrap_or(5),
+        },
+        rules: rules::Rules::new(session, rules),
+        history: history::History::new(session, config.history.limit.unwrap_or(1000)),
+        dnd: dnd::Dnd {
+            session,
+            enabled: dnd::load(session),
+            bypass_critical: config.dnd.bypass_critical.unwrap_or(true),
+            on_end: config.dnd.on_end.clone().unwrap_or("summary".to_string()),
+        },
+        sound: config.optional.sound.unwrap_or(false),
+        reload_visible: config.optional.reload_visible.unwrap_or(true),
+    }
 }
-#[derive(Debug, Deserialize)]
-struct Title {
-    color: Option<String>,
-    font_size: Option<i32>,
-    x: Option<i32>,
-    y: Option<i32>,
+
+fn print_dnd(enabled: bool, held: usize) {
+    match (enabled, held) {
+        (false, _) => println!("off"),
+        (true, 0) => println!("on"),
+        (true, held) => println!("on ({held} held back)"),
+    }
 }
-#[derive(Debug, Deserialize)]
-struct Message {
-    color: Option<String>,
-    font_size: Option<i32>,
-    x: Option<i32>,
-    y: Option<i32>,
+
+// Prints what the caller asked for about a reply and returns the exit code once
+// nothing more is expected from the server.
+fn report(reply: protocol::Reply, wait: bool, print_id: bool) -> Option<i32> {
+    match reply {
+        protocol::Reply::Ok => Some(0),
+        protocol::Reply::Dnd { enabled, held } => {
+            print_dnd(enabled, held);
+            Some(0)
+        }
+        protocol::Reply::Shown { id } => {
+            if print_id {
+                println!("{id}");
+            }
+            (!wait).then_some(0)
+        }
+        protocol::Reply::Action { key, .. } => {
+            println!("{key}");
+            Some(0)
+        }
+        protocol::Reply::Closed { reason, .. } => {
+            println!("{reason}");
+            Some(reason as i32)
+        }
+        protocol::Reply::Error { message } => {
+            eprintln!("Request rejected: {message}");
+            Some(FAILURE)
+        }
+    }
 }
-#[derive(Debug, Deserialize)]
-struct Pywal {
-    pywal: Option<bool>,
-    background_color: Option<String>,
-    border_color: Option<String>,
-    title_color: Option<String>,
-    message_color: Option<String>,
+
+// Parses the command line like argh::from_env, but a usage error exits with FAILURE.
+fn args() -> Arg {
+    let strings: Vec<String> = std::env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let cmd = strings
+        .first()
+        .and_then(|cmd| cmd.rsplit('/').next())
+        .unwrap_or("pino");
+    let strs: Vec<&str> = strings.iter().skip(1).map(String::as_str).collect();
+    Arg::from_args(&[cmd], &strs).unwrap_or_else(|early_exit| match early_exit.status {
+        Ok(()) => {
+            println!("{}", early_exit.output);
+            process::exit(0);
+        }
+        Err(()) => {
+            eprintln!(
+                "{}\nRun {cmd} --help for more information.",
+                early_exit.output
+            );
+            process::exit(FAILURE);
+        }
+    })
+}
+
+fn send(stream: &mut UnixStream, request: &protocol::Request, wait: bool, print_id: bool) -> i32 {
+    if let Err(e) = protocol::write_frame(stream, request) {
+        eprintln!("Can't reach the running instance: {e}");
+        return FAILURE;
+    }
+    loop {
+        match protocol::read_frame(stream) {
+            Ok(reply) => {
+                if let Some(code) = report(reply, wait, print_id) {
+                    return code;
+                }
+            }
+            Err(e) => {
+                eprintln!("No reply from the running instance: {e}");
+                return FAILURE;
+            }
+        }
+    }
 }
 
 fn main() {
     let config_folder = colors::get_config_dir();
-    let args: Arg = argh::from_env();
+    let args = args();
 
     if args.version {
         println!("v1.1.4");
@@ -123,126 +446,206 @@ fn main() {
         return;
     }
 
-    let config_file = match args.config {
-        Some(path) => path,
+    // A system-wide config is the base to build on, so the template would only hide it.
+    let config_file = match &args.config {
+        Some(path) => PathBuf::from(path),
         None => {
-            if !config_folder.join("pino").exists() {
+            if !config_folder.join("pino").exists() && config::system_files().is_empty() {
                 config::generate_config(config_folder.clone());
             }
-            config_folder
-                .join("pino")
-                .join("config.toml")
-                .to_string_lossy()
-                .into_owned()
+            config_folder.join("pino").join("config.toml")
         }
     };
+    let layers = |session| config::layers(&config_file, args.config.is_some(), session);
 
-    let config_content = fs::read_to_string(config_file).expect("Faild ");
-    let config: Config = toml::from_str(&config_content).expect("Faild");
-
-    let socket = format!("/tmp/pino-check-{}.sock", args.session.unwrap_or(0));
-    if let Ok(mut stream) = UnixStream::connect(&socket) {
-        stream
-            .write_all(
-                format!(
-                    "{}|+|{}|+|{}",
-                    args.title.unwrap_or("Title".to_string()),
-                    args.message
-                        .unwrap_or("you didn't set the title or message".to_string()),
-                    args.delay.unwrap_or(config.screen.delay.unwrap_or(3))
-                )
-                .as_bytes(),
-            )
-            .unwrap()
-    } else {
-        let fallback = (
-            config
-                .frame
-                .fg_color
-                .clone()
-                .unwrap_or("#000000".to_string()),
-            config.border.color.clone().unwrap_or("#62777d".to_string()),
-            config.title.color.clone().unwrap_or("#b8b8b8".to_string()),
-            config
-                .message
-                .color
-                .clone()
-                .unwrap_or("#501701".to_string()),
-        );
-
-        let colors = if config.pywal.pywal.unwrap_or(false) {
-            colors::pywal(
-                config
-                    .pywal
-                    .background_color
-                    .clone()
-                    .unwrap_or_else(|| fallback.0.clone()),
-                config
-                    .pywal
-                    .border_color
-                    .clone()
-                    .unwrap_or_else(|| fallback.1.clone()),
-                config
-                    .pywal
-                    .title_color
-                    .clone()
-                    .unwrap_or_else(|| fallback.2.clone()),
-                config
-                    .pywal
-                    .message_color
-                    .clone()
-                    .unwrap_or_else(|| fallback.3.clone()),
-            )
+    if let Some(Subcommand::Config(ConfigArgs {
+        command: ConfigCommand::Check(check),
+    })) = &args.command
+    {
+        let paths = match &check.path {
+            Some(path) => vec![PathBuf::from(path)],
+            None => layers(args.session.unwrap_or(0)),
+        };
+        let (files, problems) = config::check(&paths);
+        for problem in &problems {
+            println!("{problem}");
+        }
+        if !problems.is_empty() {
+            process::exit(1);
+        }
+        for file in &files {
+            println!("{}: OK", file.display());
+        }
+        if files.is_empty() {
+            println!("No config file, pino uses the built-in defaults");
+        }
+        return;
+    }
+
+    if let Some(Subcommand::Config(ConfigArgs {
+        command: ConfigCommand::Dump(_),
+    })) = &args.command
+    {
+        let session = args.session.unwrap_or(0);
+        let (config, _) = config::load(&layers(session));
+        let settings = settings(&config, session);
+        match toml::to_string_pretty(&config::dump(&settings, &config.rule)) {
+            Ok(dump) => print!("{dump}"),
+            Err(e) => {
+                eprintln!("Can't print the configuration: {e}");
+                process::exit(FAILURE);
+            }
+        }
+        return;
+    }
+
+    let mut replay = None;
+    if let Some(Subcommand::History(query)) = &args.command {
+        let mut entries = history::load().unwrap_or_else(|e| {
+            eprintln!("Can't read {}: {e}", history::path().display());
+            process::exit(FAILURE);
+        });
+        if let Some(id) = query.replay {
+            match entries.into_iter().find(|entry| entry.id == id) {
+                Some(entry) => replay = Some(entry),
+                None => {
+                    eprintln!("No notification with history ID {id}");
+                    process::exit(FAILURE);
+                }
+            }
         } else {
-            fallback
+            if let Some(since) = &query.since {
+                let since = history::parse_since(since).unwrap_or_else(|e| {
+                    eprintln!("Invalid --since: {e}");
+                    process::exit(FAILURE);
+                });
+                entries.retain(|entry| entry.time >= since);
+            }
+            if let Some(last) = query.last {
+                entries.drain(..entries.len().saturating_sub(last));
+            }
+            if let Err(e) = history::print(&entries, query.json) {
+                eprintln!("Can't print the history: {e}");
+                process::exit(FAILURE);
+            }
+            return;
+        }
+    }
+
+    // A replayed notification goes back to the session it was shown on unless -s says otherwise.
+    let session = args
+        .session
+        .or(replay.as_ref().map(|entry| entry.session))
+        .unwrap_or(0);
+    let socket = server::socket_path(session);
+
+    let control = if args.quit {
+        Some((socket.clone(), protocol::Request::Quit))
+    } else if args.reload {
+        Some((socket.clone(), protocol::Request::Reload))
+    } else if let Some(id) = args.close {
+        Some((socket.clone(), protocol::Request::Close { id }))
+    } else if args.close_all {
+        Some((socket.clone(), protocol::Request::CloseAll))
+    } else if let Some(mode) = args.dnd {
+        // Without a server there is nothing held back, only the saved state to change.
+        if !server::is_running(&socket) {
+            let enabled = match mode {
+                protocol::DndMode::On => true,
+                protocol::DndMode::Off => false,
+                protocol::DndMode::Toggle => !dnd::load(session),
+                protocol::DndMode::Status => dnd::load(session),
+            };
+            if mode != protocol::DndMode::Status {
+                dnd::save(session, enabled);
+            }
+            print_dnd(enabled, 0);
+            return;
+        }
+        Some((socket.clone(), protocol::Request::Dnd { mode }))
+    } else {
+        args.close_session
+            .map(|session| (server::socket_path(session), protocol::Request::CloseAll))
+    };
+    if let Some((socket, request)) = control {
+        match UnixStream::connect(&socket) {
+            Ok(mut stream) => process::exit(send(&mut stream, &request, false, false)),
+            Err(_) => {
+                eprintln!("No pino instance is running on {socket}");
+                process::exit(FAILURE);
+            }
+        }
+    }
+
+    if args.daemon && server::is_running(&socket) {
+        eprintln!("A pino instance is already running on {socket}");
+        process::exit(FAILURE);
+    }
+
+    let notification = replay.map(|entry| entry.notification);
+    let notification = notification.unwrap_or_else(|| protocol::Notification {
+        title: args.title.unwrap_or("Title".to_string()),
+        message: args
+            .message
+            .unwrap_or("you didn't set the title or message".to_string()),
+        markup: args.markup,
+        delay: args.delay,
+        urgency: args.urgency.unwrap_or_default(),
+        icon: args.icon.map(protocol::Icon::Name),
+        progress: args.progress.map(|value| value.min(100)),
+        actions: args
+            .action
+            .iter()
+            .map(|action| {
+                let (key, label) = action.split_once('=').unwrap_or((action, action));
+                protocol::Action {
+                    key: key.to_string(),
+                    label: label.to_string(),
+                }
+            })
+            .collect(),
+        app_name: None,
+        sound: args.sound,
+    });
+
+    let stream = if args.daemon {
+        None
+    } else {
+        UnixStream::connect(&socket).ok()
+    };
+    if let Some(mut stream) = stream {
+        let request = protocol::Request::Notify {
+            notification,
+            replaces: args.replace_id,
+            wait: args.wait,
         };
+        process::exit(send(&mut stream, &request, args.wait, args.print_id));
+    } else {
+        let (reply, replies) = mpsc::channel();
+        let first = (!args.daemon).then(|| server::Command::Notify {
+            notification,
+            replaces: None,
+            wait: args.wait,
+            reply,
+        });
+        let (wait, print_id) = (args.wait, args.print_id);
+        let waiter = thread::spawn(move || {
+            replies
+                .into_iter()
+                .find_map(|reply| report(reply, wait, print_id))
+                .unwrap_or(0)
+        });
 
-        let (sx, sy, sw, sh) = screen_xywh(config.screen.monitor.unwrap_or(0));
-        let (ax, ay, aw, ah) = (
-            config.screen.x.unwrap_or(25),
-            config.screen.y.unwrap_or(55),
-            config.screen.width.unwrap_or(400),
-            config.screen.height.unwrap_or(60),
-        );
-        let screen = match config
-            .screen
-            .placement
-            .unwrap_or("top_center".to_string())
-            .as_str()
-        {
-            "top_left" => (sx + ax, sy + ay),
-            "top_center" => (sx + (sw - aw) / 2, sy + ay),
-            "top_right" => (sx + sw - aw - ax, sy + ay),
-            "bottom_left" => (sx + ax, sy + sh - ah - ay),
-            "bottom_center" => (sx + (sw - aw) / 2, sy + sh - ah - ay),
-            "bottom_right" => (sx + sw - aw - ax, sy + sh - ah - ay),
-            _ => (20, 30),
+        // The files are watched even before they exist, so creating one counts as a change.
+        let (user, required) = (config_file.clone(), args.config.is_some());
+        let load = move || {
+            let (config, mut files) = config::load(&config::layers(&user, required, session));
+            files.extend([user.clone(), config::overlay(&user, session)]);
+            // The palette too, so a new wallpaper or theme re-colors the notifications.
+            files.extend(config.theme_file());
+            (settings(&config, session), files)
         };
-        ui::ui(
-            (screen.0, screen.1, aw, ah),
-            config.frame.font_family.unwrap_or("Monospace".to_string()),
-            (
-                config.border.weight.unwrap_or(2),
-                config.border.radius.unwrap_or(10),
-            ),
-            (
-                config.title.x.unwrap_or(5),
-                config.title.y.unwrap_or(0),
-                config.title.font_size.unwrap_or(17),
-            ),
-            (
-                config.message.x.unwrap_or(10),
-                config.message.y.unwrap_or(19),
-                config.message.font_size.unwrap_or(13),
-            ),
-            colors,
-            (
-                args.title.unwrap_or("Title".to_string()),
-                args.message
-                    .unwrap_or("you didn't set the title or message".to_string()),
-                args.delay.unwrap_or(config.screen.delay.unwrap_or(5)),
-            ),
-            socket,
-        );
+        ui::ui(load, first, socket, args.daemon);
+        process::exit(waiter.join().unwrap_or(0));
     }
 }
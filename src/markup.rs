@@ -0,0 +1,232 @@
+// The body markup of the freedesktop notification spec: <b>, <i>, <u>, <a href="...">
+// and <img src="..." alt="...">, with XML entities. Anything else is dropped.
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub link: Option<String>,
+}
+
+impl Span {
+    fn same_style(&self, other: &Span) -> bool {
+        self.bold == other.bold
+            && self.italic == other.italic
+            && self.underline == other.underline
+            && self.link == other.link
+    }
+}
+
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+// Value of `name="..."` (or single quoted) inside a tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(name) {
+        let start = from + found;
+        from = start + name.len();
+        let before = lower[..start].chars().next_back();
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let rest = tag[from..].trim_start().strip_prefix('=')?.trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &rest[1..];
+        return Some(decode_entities(&value[..value.find(quote)?]));
+    }
+    None
+}
+
+// Appends text in `style`, merging it with the previous span if that has the same style.
+fn push(spans: &mut Vec<Span>, style: &Span, text: String) {
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.same_style(style) => last.text.push_str(&text),
+        _ => spans.push(Span {
+            text,
+            ..style.clone()
+        }),
+    }
+}
+
+pub fn parse(body: &str) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut style = Span::default();
+    let (mut bold, mut italic, mut underline) = (0, 0, 0);
+    let mut rest = body;
+    while let Some(open) = rest.find('<') {
+        push(&mut spans, &style, decode_entities(&rest[..open]));
+        rest = &rest[open..];
+        // A `<` that doesn't start a tag is just text.
+        let is_tag = rest[1..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '/');
+        let Some(close) = rest.find('>').filter(|_| is_tag) else {
+            push(&mut spans, &style, "<".to_string());
+            rest = &rest[1..];
+            continue;
+        };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag.trim_end_matches('/')),
+        };
+        let name = tag
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let depth = if closing { -1 } else { 1 };
+        match name.as_str() {
+            "b" => bold = (bold + depth).max(0),
+            "i" => italic = (italic + depth).max(0),
+            "u" => underline = (underline + depth).max(0),
+            "a" if closing => style.link = None,
+            "a" => style.link = attribute(tag, "href"),
+            "br" => push(&mut spans, &style, "\n".to_string()),
+            // Images aren't drawn, their alternative text stands in for them.
+            "img" => {
+                if let Some(alt) = attribute(tag, "alt") {
+                    push(&mut spans, &style, alt);
+                }
+            }
+            _ => {}
+        }
+        style.bold = bold > 0;
+        style.italic = italic > 0;
+        style.underline = underline > 0;
+    }
+    push(&mut spans, &style, decode_entities(rest));
+    spans
+}
+
+// The text of the body with the markup removed.
+pub fn strip(body: &str) -> String {
+    parse(body).into_iter().map(|span| span.text).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str) -> Span {
+        Span {
+            text: text.to_string(),
+            ..Span::default()
+        }
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            decode_entities("a &lt;b&gt; &amp; &quot;c&apos;"),
+            "a <b> & \"c'"
+        );
+        assert_eq!(decode_entities("&#65;&#x42;&#X43;&nbsp;"), "ABC\u{a0}");
+    }
+
+    #[test]
+    fn keeps_stray_ampersands() {
+        assert_eq!(decode_entities("Tom & Jerry"), "Tom & Jerry");
+        assert_eq!(decode_entities("&bogus; &#xzz; &"), "&bogus; &#xzz; &");
+        assert_eq!(decode_entities("& a long way; off"), "& a long way; off");
+        assert_eq!(decode_entities("é&é;&amp;"), "é&é;&");
+    }
+
+    #[test]
+    fn parses_styles() {
+        let bold = Span {
+            bold: true,
+            ..span("bold ")
+        };
+        let both = Span {
+            bold: true,
+            italic: true,
+            ..span("both")
+        };
+        let link = Span {
+            link: Some("https://example.com/?a=1&b=2".to_string()),
+            ..span("site")
+        };
+        assert_eq!(
+            parse(r#"<b>bold <i>both</i></b> <a href="https://example.com/?a=1&amp;b=2">site</a>"#),
+            vec![bold, both, span(" "), link]
+        );
+    }
+
+    #[test]
+    fn parses_breaks_images_and_unknown_tags() {
+        assert_eq!(
+            parse(r#"one<br/>two <img src="x.png" alt="[pic]"/> <span>three</span>"#),
+            vec![span("one\ntwo [pic] three")]
+        );
+    }
+
+    #[test]
+    fn keeps_stray_angle_brackets() {
+        assert_eq!(parse("1 < 2 > 0"), vec![span("1 < 2 > 0")]);
+        assert_eq!(parse("a <b"), vec![span("a <b")]);
+        assert_eq!(parse("ends with <"), vec![span("ends with <")]);
+        assert_eq!(parse("x &lt;b&gt; y"), vec![span("x <b> y")]);
+    }
+
+    #[test]
+    fn ignores_unbalanced_closing_tags() {
+        let bold = Span {
+            bold: true,
+            ..span("bold")
+        };
+        assert_eq!(parse("</b></b>plain<b>bold"), vec![span("plain"), bold]);
+        assert_eq!(strip("<i>a</i>&amp;<u>b</u>"), "a&b");
+    }
+}
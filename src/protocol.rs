@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+// Every frame on the session socket is a 4 byte big-endian length followed by
+// a JSON envelope of that many bytes: {"version": VERSION, "body": ...}
+pub const VERSION: u32 = 1;
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+    // Render the freedesktop body markup in `message`; without it the tags are stripped.
+    pub markup: bool,
+    // None uses the delay the server has configured for the urgency, 0 never expires.
+    pub delay: Option<u64>,
+    pub urgency: Urgency,
+    pub icon: Option<Icon>,
+    // 0-100, drawn as a bar under the text
+    pub progress: Option<u8>,
+    // An action keyed "default" is invoked by clicking the notification itself.
+    pub actions: Vec<Action>,
+    // The sending application, as D-Bus clients name it
+    pub app_name: Option<String>,
+    // A sound file or sound theme name. None plays the configured sound, "" nothing.
+    pub sound: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Action {
+    pub key: String,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Icon {
+    // A file path, file:// URI or freedesktop icon theme name
+    Name(String),
+    // Raw pixels as sent in the freedesktop `image-data` hint
+    Data(ImageData),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageData {
+    pub width: i32,
+    pub height: i32,
+    pub rowstride: i32,
+    pub has_alpha: bool,
+    pub bits_per_sample: i32,
+    pub channels: i32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+impl FromStr for Urgency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Urgency::Low),
+            "normal" => Ok(Urgency::Normal),
+            "critical" => Ok(Urgency::Critical),
+            _ => Err(format!(
+                "unknown urgency `{s}` (expected low, normal or critical)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Urgency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DndMode {
+    On,
+    Off,
+    Toggle,
+    Status,
+}
+
+impl FromStr for DndMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "on" => Ok(DndMode::On),
+            "off" => Ok(DndMode::Off),
+            "toggle" => Ok(DndMode::Toggle),
+            "status" => Ok(DndMode::Status),
+            _ => Err(format!(
+                "unknown mode `{s}` (expected on, off, toggle or status)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    // With `wait` the connection stays open until the notification closes.
+    Notify {
+        notification: Notification,
+        replaces: Option<u32>,
+        wait: bool,
+    },
+    Close {
+        id: u32,
+    },
+    CloseAll,
+    Dnd {
+        mode: DndMode,
+    },
+    // Reads the config files again.
+    Reload,
+    Quit,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reply {
+    Ok,
+    Shown { id: u32 },
+    Action { id: u32, key: String },
+    Closed { id: u32, reason: CloseReason },
+    // The do-not-disturb state and how many notifications it holds back
+    Dnd { enabled: bool, held: usize },
+    Error { message: String },
+}
+
+// The numeric values follow the freedesktop NotificationClosed reason codes and
+// are what `pino --wait` exits with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    Expired = 1,
+    Dismissed = 2,
+    Closed = 3,
+    Replaced = 4,
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CloseReason::Expired => "expired",
+            CloseReason::Dismissed => "dismissed",
+            CloseReason::Closed => "closed",
+            CloseReason::Replaced => "replaced",
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    body: T,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    TooLarge(usize),
+    Version(u32),
+    Malformed(serde_json::Error),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(f, "socket error: {e}"),
+            ProtocolError::TooLarge(n) => {
+                write!(f, "frame of {n} bytes exceeds the {MAX_FRAME} byte limit")
+            }
+            ProtocolError::Version(v) => {
+                write!(f, "unsupported protocol version {v} (expected {VERSION})")
+            }
+            ProtocolError::Malformed(e) => write!(f, "malformed frame: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        ProtocolError::Io(e)
+    }
+}
+
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, body: &T) -> io::Result<()> {
+    let payload = serde_json::to_vec(&Envelope {
+        version: VERSION,
+        body,
+    })?;
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+pub fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T, ProtocolError> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(ProtocolError::TooLarge(len));
+    }
+
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+
+    let header: Header = serde_json::from_slice(&payload).map_err(ProtocolError::Malformed)?;
+    if header.version != VERSION {
+        return Err(ProtocolError::Version(header.version));
+    }
+    let envelope: Envelope<T> =
+        serde_json::from_slice(&payload).map_err(ProtocolError::Malformed)?;
+    Ok(envelope.body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, &Request::Close { id: 7 }).unwrap();
+        write_frame(
+            &mut bytes,
+            &Reply::Closed {
+                id: 7,
+                reason: CloseReason::Dismissed,
+            },
+        )
+        .unwrap();
+
+        let mut reader = bytes.as_slice();
+        let request: Request = read_frame(&mut reader).unwrap();
+        assert!(matches!(request, Request::Close { id: 7 }));
+        let reply: Reply = read_frame(&mut reader).unwrap();
+        assert!(matches!(
+            reply,
+            Reply::Closed {
+                id: 7,
+                reason: CloseReason::Dismissed
+            }
+        ));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn frame_layout() {
+        let mut bytes = Vec::new();
+        write_frame(&mut bytes, &Request::Quit).unwrap();
+        let expected = format!(r#"{{"version":{VERSION},"body":{{"type":"quit"}}}}"#);
+        assert_eq!(bytes, frame(expected.as_bytes()));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let bytes = frame(br#"{"version":2,"body":{"type":"quit"}}"#);
+        let result: Result<Request, _> = read_frame(&mut bytes.as_slice());
+        assert!(matches!(result, Err(ProtocolError::Version(2))));
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        for payload in [
+            &b"not json"[..],
+            br#"{"body":{"type":"quit"}}"#,
+            br#"{"version":1,"body":{"type":"launch"}}"#,
+            br#"{"version":1,"body":{"type":"close"}}"#,
+        ] {
+            let result: Result<Request, _> = read_frame(&mut frame(payload).as_slice());
+            assert!(
+                matches!(result, Err(ProtocolError::Malformed(_))),
+                "{payload:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_and_truncated_frames() {
+        let bytes = ((MAX_FRAME + 1) as u32).to_be_bytes();
+        let result: Result<Request, _> = read_frame(&mut bytes.as_slice());
+        assert!(matches!(result, Err(ProtocolError::TooLarge(_))));
+
+        let mut bytes = frame(br#"{"version":1,"body":{"type":"quit"}}"#);
+        bytes.truncate(bytes.len() - 1);
+        let result: Result<Request, _> = read_frame(&mut bytes.as_slice());
+        assert!(matches!(result, Err(ProtocolError::Io(_))));
+    }
+}
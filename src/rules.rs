@@ -0,0 +1,317 @@
+use crate::{
+    config::StyleOverride,
+    protocol::{Notification, Urgency},
+};
+use regex::Regex;
+use std::sync::LazyLock;
+
+// Stands in for a missing title or body pattern, so `$0` is the whole text.
+static WHOLE: LazyLock<Regex> = LazyLock::new(|| Regex::new("(?s).*").unwrap());
+
+// A `[[rule]]` of the config. Every pattern that is set has to match, then the
+// rewrites and overrides apply.
+pub struct Rule {
+    pub app: Option<Regex>,
+    pub title: Option<Regex>,
+    pub body: Option<Regex>,
+    pub urgency: Option<Urgency>,
+    pub session: Option<u8>,
+
+    pub suppress: bool,
+    // templates where $1, ${name} are the groups of the pattern of the same field, or
+    // of the other one when that isn't set
+    pub set_title: Option<String>,
+    pub set_body: Option<String>,
+    pub delay: Option<u64>,
+    pub sound: Option<String>,
+    pub placement: Option<String>,
+    pub style: Option<StyleOverride>,
+}
+
+// What the matching rules decided about showing a notification.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Outcome {
+    pub suppress: bool,
+    pub placement: Option<String>,
+    // applied in order over the style of the urgency
+    pub styles: Vec<StyleOverride>,
+}
+
+pub struct Rules {
+    session: u8,
+    rules: Vec<Rule>,
+}
+
+// Fills `template` with the groups of the pattern of its own field, or of the other
+// field's pattern when its own is unset. Without either, `$0` is the whole text.
+fn expand(own: (Option<&Regex>, &str), other: (Option<&Regex>, &str), template: &str) -> String {
+    let (pattern, text) = match (own, other) {
+        ((Some(pattern), text), _) | ((None, _), (Some(pattern), text)) => (pattern, text),
+        ((None, text), (None, _)) => (&*WHOLE, text),
+    };
+    let mut out = String::new();
+    if let Some(captures) = pattern.captures(text) {
+        captures.expand(template, &mut out);
+    }
+    out
+}
+
+impl Rule {
+    fn matches(&self, notification: &Notification, session: u8) -> bool {
+        // Notifications sent with the pino command have no app name of their own.
+        let app = notification.app_name.as_deref().unwrap_or("pino");
+        self.app.as_ref().is_none_or(|app_re| app_re.is_match(app))
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|title| title.is_match(&notification.title))
+            && self
+                .body
+                .as_ref()
+                .is_none_or(|body| body.is_match(&notification.message))
+            && self.urgency.is_none_or(|u| u == notification.urgency)
+            && self.session.is_none_or(|s| s == session)
+    }
+}
+
+impl Rules {
+    pub fn new(session: u8, rules: Vec<Rule>) -> Rules {
+        Rules { session, rules }
+    }
+
+    // Runs every rule that matches, in the order of the config, rewriting the
+    // notification as they go. A suppressing rule ends the run.
+    pub fn apply(&self, notification: &mut Notification) -> Outcome {
+        let mut outcome = Outcome::default();
+        // A sound the notification asked for, or silence, stays over the ones of the rules.
+        let asked_sound = notification.sound.is_some();
+        for rule in &self.rules {
+            if !rule.matches(notification, self.session) {
+                continue;
+            }
+            if rule.suppress {
+                outcome.suppress = true;
+                break;
+            }
+            // Both rewrites take their groups from the text as it was before the rule.
+            let (title, body) = (notification.title.clone(), notification.message.clone());
+            let (title, body) = ((rule.title.as_ref(), &*title), (rule.body.as_ref(), &*body));
+            if let Some(template) = &rule.set_title {
+                notification.title = expand(title, body, template);
+            }
+            if let Some(template) = &rule.set_body {
+                notification.message = expand(body, title, template);
+            }
+            if rule.delay.is_some() {
+                notification.delay = rule.delay;
+            }
+            if rule.sound.is_some() && !asked_sound {
+                notification.sound = rule.sound.clone();
+            }
+            if let Some(placement) = &rule.placement {
+                outcome.placement = Some(placement.clone());
+            }
+            if let Some(style) = &rule.style {
+                outcome.styles.push(style.clone());
+            }
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule() -> Rule {
+        Rule {
+            app: None,
+            title: None,
+            body: None,
+            urgency: None,
+            session: None,
+            suppress: false,
+            set_title: None,
+            set_body: None,
+            delay: None,
+            sound: None,
+            placement: None,
+            style: None,
+        }
+    }
+
+    fn regex(pattern: &str) -> Option<Regex> {
+        Some(Regex::new(pattern).unwrap())
+    }
+
+    fn notification(title: &str, message: &str) -> Notification {
+        Notification {
+            title: title.to_string(),
+            message: message.to_string(),
+            markup: false,
+            delay: None,
+            urgency: Urgency::Normal,
+            icon: None,
+            progress: None,
+            actions: Vec::new(),
+            app_name: None,
+            sound: None,
+        }
+    }
+
+    fn apply(rules: Vec<Rule>, notification: &mut Notification) -> Outcome {
+        Rules::new(0, rules).apply(notification)
+    }
+
+    #[test]
+    fn expands_groups_of_the_same_field() {
+        let mut n = notification("Build 42 failed", "see the log");
+        apply(
+            vec![Rule {
+                title: regex(r"Build (?<number>\d+) (\w+)"),
+                body: regex("the (log)"),
+                set_title: Some("$2: #${number}".to_string()),
+                set_body: Some("open $1".to_string()),
+                ..rule()
+            }],
+            &mut n,
+        );
+        assert_eq!(
+            (n.title.as_str(), n.message.as_str()),
+            ("failed: #42", "open log")
+        );
+    }
+
+    #[test]
+    fn expands_groups_of_the_other_field() {
+        let mut n = notification("Your code is 1234", "ignored");
+        apply(
+            vec![Rule {
+                title: regex(r"^Your code is (\d+)"),
+                set_body: Some("Code $1".to_string()),
+                ..rule()
+            }],
+            &mut n,
+        );
+        assert_eq!(n.message, "Code 1234");
+
+        let mut n = notification("Mail", "from Ann");
+        apply(
+            vec![Rule {
+                body: regex("from (\\w+)"),
+                set_title: Some("$0 ($1)".to_string()),
+                ..rule()
+            }],
+            &mut n,
+        );
+        assert_eq!(n.title, "from Ann (Ann)");
+    }
+
+    #[test]
+    fn whole_text_without_patterns() {
+        let mut n = notification("hi", "there");
+        n.app_name = Some("Discord".to_string());
+        apply(
+            vec![Rule {
+                app: regex("(?i)discord"),
+                set_title: Some("Discord: $0".to_string()),
+                set_body: Some("<$0>".to_string()),
+                ..rule()
+            }],
+            &mut n,
+        );
+        assert_eq!(
+            (n.title.as_str(), n.message.as_str()),
+            ("Discord: hi", "<there>")
+        );
+    }
+
+    #[test]
+    fn rules_apply_in_order() {
+        let mut n = notification("a", "");
+        let outcome = apply(
+            vec![
+                Rule {
+                    set_title: Some("${0}b".to_string()),
+                    delay: Some(3),
+                    placement: Some("top_left".to_string()),
+                    style: Some(StyleOverride::default()),
+                    ..rule()
+                },
+                // sees what the first rule made of the title
+                Rule {
+                    title: regex("^ab$"),
+                    set_title: Some("${0}c".to_string()),
+                    delay: Some(5),
+                    placement: Some("bottom_right".to_string()),
+                    style: Some(StyleOverride::default()),
+                    ..rule()
+                },
+                Rule {
+                    title: regex("^a$"),
+                    set_title: Some("never".to_string()),
+                    ..rule()
+                },
+            ],
+            &mut n,
+        );
+        assert_eq!(n.title, "abc");
+        assert_eq!(n.delay, Some(5));
+        assert_eq!(outcome.placement.as_deref(), Some("bottom_right"));
+        assert_eq!(outcome.styles.len(), 2);
+        assert!(!outcome.suppress);
+    }
+
+    #[test]
+    fn suppress_ends_the_run() {
+        let mut n = notification("a", "");
+        let outcome = apply(
+            vec![
+                Rule {
+                    suppress: true,
+                    ..rule()
+                },
+                Rule {
+                    set_title: Some("b".to_string()),
+                    ..rule()
+                },
+            ],
+            &mut n,
+        );
+        assert!(outcome.suppress);
+        assert_eq!(n.title, "a");
+    }
+
+    #[test]
+    fn matches_app_session_and_urgency() {
+        let rules = || {
+            vec![
+                Rule {
+                    app: regex("^pino$"),
+                    urgency: Some(Urgency::Critical),
+                    set_title: Some("critical".to_string()),
+                    ..rule()
+                },
+                Rule {
+                    session: Some(1),
+                    set_body: Some("session 1".to_string()),
+                    ..rule()
+                },
+            ]
+        };
+        let mut n = notification("t", "m");
+        Rules::new(1, rules()).apply(&mut n);
+        assert_eq!((n.title.as_str(), n.message.as_str()), ("t", "session 1"));
+
+        let mut n = notification("t", "m");
+        n.urgency = Urgency::Critical;
+        Rules::new(0, rules()).apply(&mut n);
+        assert_eq!((n.title.as_str(), n.message.as_str()), ("critical", "m"));
+
+        let mut n = notification("t", "m");
+        n.urgency = Urgency::Critical;
+        n.app_name = Some("firefox".to_string());
+        Rules::new(0, rules()).apply(&mut n);
+        assert_eq!(n.title, "t");
+    }
+}
@@ -0,0 +1,114 @@
+use crate::protocol::{self, DndMode, Notification, Reply, Request};
+use fltk::app;
+use std::{
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+// Every command carries the sender its replies are written back through; the
+// connection is closed once the UI thread drops it.
+pub enum Command {
+    Notify {
+        notification: Notification,
+        replaces: Option<u32>,
+        wait: bool,
+        reply: Sender<Reply>,
+    },
+    Close {
+        id: u32,
+        reply: Sender<Reply>,
+    },
+    CloseAll {
+        reply: Sender<Reply>,
+    },
+    Dnd {
+        mode: DndMode,
+        reply: Sender<Reply>,
+    },
+    Reload {
+        reply: Sender<Reply>,
+    },
+    Quit {
+        reply: Sender<Reply>,
+    },
+}
+
+pub fn socket_path(session: u8) -> String {
+    format!("/tmp/pino-check-{session}.sock")
+}
+
+pub fn is_running(socket: &str) -> bool {
+    UnixStream::connect(socket).is_ok()
+}
+
+fn handle(mut stream: UnixStream, tx: Sender<Command>) {
+    let (reply, replies) = mpsc::channel();
+    let command = match protocol::read_frame(&mut stream) {
+        Ok(Request::Notify {
+            notification,
+            replaces,
+            wait,
+        }) => Command::Notify {
+            notification,
+            replaces,
+            wait,
+            reply,
+        },
+        Ok(Request::Close { id }) => Command::Close { id, reply },
+        Ok(Request::CloseAll) => Command::CloseAll { reply },
+        Ok(Request::Dnd { mode }) => Command::Dnd { mode, reply },
+        Ok(Request::Reload) => Command::Reload { reply },
+        Ok(Request::Quit) => Command::Quit { reply },
+        Err(e) => {
+            eprintln!("Rejected request: {e}");
+            let _ = protocol::write_frame(
+                &mut stream,
+                &Reply::Error {
+                    message: e.to_string(),
+                },
+            );
+            return;
+        }
+    };
+    if tx.send(command).is_err() {
+        return;
+    }
+    app::awake();
+
+    for reply in replies {
+        if protocol::write_frame(&mut stream, &reply).is_err() {
+            break;
+        }
+    }
+}
+
+// Binds the session socket and forwards every decoded request to the UI thread.
+pub fn listen(socket: &str, tx: Sender<Command>) {
+    if Path::new(socket).exists() {
+        std::fs::remove_file(socket).unwrap();
+    }
+    let listener = UnixListener::bind(socket).unwrap();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let tx = tx.clone();
+                    thread::spawn(move || handle(stream, tx));
+                }
+                Err(e) => {
+                    eprintln!("Error accepting connection: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+pub fn cleanup(socket: &str) {
+    if Path::new(socket).exists() {
+        std::fs::remove_file(socket).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use crate::xdg;
+use std::{path::PathBuf, sync::mpsc::Sender};
+
+const EXTENSIONS: [&str; 3] = ["oga", "ogg", "wav"];
+
+// Plays sounds on a thread of its own, so opening the audio device and decoding
+// never hold up the UI loop.
+pub struct Player {
+    files: Option<Sender<PathBuf>>,
+}
+
+// Sound names like "message-new-instant" come from the freedesktop sound theme.
+fn resolve(sound: &str) -> Option<PathBuf> {
+    if let Some(path) = xdg::file(sound) {
+        return path.exists().then(|| path.to_path_buf());
+    }
+    xdg::data_dirs().into_iter().find_map(|dir| {
+        let dir = dir.join("sounds").join("freedesktop").join("stereo");
+        EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(format!("{sound}.{ext}")))
+            .find(|path| path.exists())
+    })
+}
+
+#[cfg(feature = "sound")]
+fn run(files: std::sync::mpsc::Receiver<PathBuf>) {
+    use rodio::{Decoder, OutputStream, Source};
+    use std::{fs::File, io::BufReader};
+
+    // The device is only opened once there is something to play, and kept open.
+    let mut output = None;
+    for path in files {
+        if output.is_none() {
+            match OutputStream::try_default() {
+                Ok(stream) => output = Some(stream),
+                Err(e) => {
+                    eprintln!("Can't open the audio output: {e}");
+                    continue;
+                }
+            }
+        }
+        let (_, handle) = output.as_ref().unwrap();
+        let source = File::open(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| Decoder::new(BufReader::new(file)).map_err(|e| e.to_string()));
+        match source {
+            Ok(source) => {
+                if let Err(e) = handle.play_raw(source.convert_samples()) {
+                    eprintln!("Can't play {}: {e}", path.display());
+                }
+            }
+            Err(e) => eprintln!("Can't play {}: {e}", path.display()),
+        }
+    }
+}
+
+impl Player {
+    // A player that stays silent unless `enabled`.
+    pub fn new(enabled: bool) -> Player {
+        if !enabled {
+            return Player { files: None };
+        }
+        #[cfg(feature = "sound")]
+        {
+            let (files, received) = std::sync::mpsc::channel();
+            std::thread::spawn(move || run(received));
+            Player { files: Some(files) }
+        }
+        #[cfg(not(feature = "sound"))]
+        {
+            eprintln!("Sounds are turned on, but pino was built without the sound feature");
+            Player { files: None }
+        }
+    }
+
+    // An empty sound plays nothing.
+    pub fn play(&self, sound: &str) {
+        let Some(files) = &self.files else {
+            return;
+        };
+        if sound.is_empty() {
+            return;
+        }
+        match resolve(sound) {
+            Some(path) => {
+                let _ = files.send(path);
+            }
+            None => eprintln!("Can't find the sound {sound}"),
+        }
+    }
+}
@@ -1,172 +1,1284 @@
+use crate::{
+    colors::Rgba,
+    dnd::{self, Dnd},
+    history::History,
+    icon,
+    markup::{self, Span},
+    protocol::{Action, CloseReason, DndMode, Notification, Reply, Urgency},
+    rules::{Outcome, Rules},
+    server::{self, Command},
+    sound::Player,
+    watch::Watcher,
+};
 use fltk::{
-    app::{self, get_font_names},
+    app::{self, get_font_names, screen_xywh},
+    button::Button,
     draw,
-    enums::{Align, Color, Event, Font},
+    enums::{Align, Color, Event, Font, FrameType},
     frame::Frame,
     prelude::*,
     window::Window,
 };
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
 use std::{
-    io::Read,
-    os::unix::net::UnixListener,
-    path::Path,
-    sync::mpsc,
-    thread,
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    env,
+    path::PathBuf,
+    process,
+    rc::Rc,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+    },
     time::{Duration, Instant},
 };
 
+#[derive(Clone)]
+pub struct Style {
+    pub font_family: String,
+    // weight, radius
+    pub border: (i32, i32),
+    // x, y, font size
+    pub title: (i32, i32, i32),
+    pub message: (i32, i32, i32),
+    // lines of message shown before it is cut with an ellipsis, 0 shows them all
+    pub max_lines: usize,
+    // background, border, title, message
+    pub colors: (String, String, String, String),
+    // seconds, 0 keeps the notification until it is dismissed
+    pub delay: u64,
+    // x, y, size
+    pub icon: (i32, i32, i32),
+    // "left" or "right" of the text
+    pub icon_position: String,
+    pub icon_theme: String,
+    // x, y (negative counts from the bottom), height, radius
+    pub progress: (i32, i32, i32, i32),
+    // bar, trough
+    pub progress_colors: (String, String),
+    // file or sound theme name played when a notification shows up, "" plays nothing
+    pub sound: String,
+}
+
+pub struct Styles {
+    // the style before the `[urgency.*]` sections
+    pub base: Style,
+    pub low: Style,
+    pub normal: Style,
+    pub critical: Style,
+}
+
+impl Styles {
+    fn get(&self, urgency: Urgency) -> &Style {
+        match urgency {
+            Urgency::Low => &self.low,
+            Urgency::Normal => &self.normal,
+            Urgency::Critical => &self.critical,
+        }
+    }
+}
+
+pub struct Layout {
+    pub monitor: i32,
+    pub placement: String,
+    // x, y distance from the screen edges
+    pub offset: (i32, i32),
+    pub size: (i32, i32),
+    // grow the height to fit the content, up to max_height
+    pub auto_height: bool,
+    pub max_height: i32,
+    pub gap: i32,
+    pub max_visible: usize,
+}
+
+// Everything the server needs from the config.
+pub struct Settings {
+    pub styles: Styles,
+    pub layout: Layout,
+    pub rules: Rules,
+    pub history: History,
+    pub dnd: Dnd,
+    // play sounds at all
+    pub sound: bool,
+    // build the popups on screen again when the config is reloaded
+    pub reload_visible: bool,
+}
+
+// A click on popup `id`, either on one of its action buttons or on the popup itself.
+type Click = (u32, Option<String>);
+
+struct Queued {
+    id: u32,
+    notification: Notification,
+    // as it was sent, before the rules rewrote it, for the history
+    sent: Notification,
+    outcome: Outcome,
+    waiter: Option<Sender<Reply>>,
+}
+
+struct Popup {
+    id: u32,
+    // what is shown right now
+    notification: Notification,
+    // as it was sent, before the rules rewrote it, for the history
+    sent: Notification,
+    window: Window,
+    title: Frame,
+    message: Frame,
+    lines: Rc<RefCell<Vec<Vec<Run>>>>,
+    wrap_width: i32,
+    icon: Option<Frame>,
+    progress: Option<Rc<Cell<u8>>>,
+    style: Style,
+    // what the rules decided when it was built
+    outcome: Outcome,
+    expires: Option<Instant>,
+    waiter: Option<Sender<Reply>>,
+}
+
+pub const PLACEMENTS: [&str; 6] = [
+    "top_left",
+    "top_center",
+    "top_right",
+    "bottom_left",
+    "bottom_center",
+    "bottom_right",
+];
+
 pub fn print_fonts() {
     for font in get_font_names() {
         println!("{font}");
     }
 }
 
-pub fn ui(
-    screen_info: (i32, i32, i32, i32),
-    font_family: String,
-    border: (i32, i32),
-    title: (i32, i32, i32),
-    message: (i32, i32, i32),
-    colors: (String, String, String, String),
-    data: (String, String, u64),
-    socket_path: String,
-) {
-    let app = app::App::default().load_system_fonts();
-
-    let mut wind1 = Window::new(
-        screen_info.0,
-        screen_info.1,
-        screen_info.2,
-        screen_info.3,
-        "Pino",
-    );
-    wind1.set_color(Color::from_hex_str(colors.1.as_str()).unwrap());
-
-    let mut wind2 = Window::new(
-        border.0 + border.1 / 2,
-        border.0 + border.1 / 2,
-        screen_info.2 - border.0 * 2 - border.1,
-        screen_info.3 - border.0 * 2 - border.1,
-        "Pino",
-    );
-    wind2.set_color(Color::from_hex_str(colors.0.as_str()).unwrap());
-
-    let mut title_frame = Frame::new(
-        5 + title.0,
-        20 + title.1,
-        wind2.w() - 10,
-        wind2.h() - 10,
-        "",
-    );
-    let mut message_frame = Frame::new(
-        5 + message.0,
-        20 + message.1,
-        wind2.w() - 10,
-        wind2.h() - 10,
-        "",
-    );
-
-    title_frame.set_align(Align::Top | Align::Left);
-    title_frame.set_label_color(Color::from_hex_str(colors.2.as_str()).unwrap());
-    title_frame.set_label_font(Font::by_name(font_family.as_str()));
-    title_frame.set_label_size(title.2);
-
-    message_frame.set_align(Align::Top | Align::Left);
-    message_frame.set_label_color(Color::from_hex_str(colors.3.as_str()).unwrap());
-    message_frame.set_label_font(Font::by_name(font_family.as_str()));
-    message_frame.set_label_size(message.2);
-
-    wind1.draw(move |f| {
-        draw::set_draw_color(Color::from_hex_str(colors.0.as_str()).unwrap());
-        draw::draw_rounded_rectf(
-            border.0 - 1,
-            border.0 - 1,
-            f.w() - border.0 * 2 + 2,
-            f.h() - border.0 * 2 + 2,
-            border.1,
-        );
+// Whether `family` names a font, rather than falling back to Helvetica. The system
+// fonts are loaded on the first call, and without a display to load them from there
+// is no answer.
+pub fn has_font(family: &str) -> Option<bool> {
+    static LOADED: OnceLock<bool> = OnceLock::new();
+    let loaded = *LOADED.get_or_init(|| {
+        let display = ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|var| env::var_os(var).is_some());
+        if display {
+            let _app = app::App::default().load_system_fonts();
+        }
+        display
     });
+    loaded.then(|| app::font_index(family).is_some())
+}
+
+// Font of `family` in the given weight and slant. The builtin fonts come in groups of
+// four and FLTK names the variants of system fonts with a B, I or P (bold italic) prefix.
+fn font_variant(family: &str, bold: bool, italic: bool) -> Font {
+    let base = Font::by_name(family);
+    let variant = bold as i32 + 2 * italic as i32;
+    if variant == 0 {
+        return base;
+    }
+    if base.bits() < 12 {
+        return Font::by_index((base.bits() - base.bits() % 4 + variant) as usize);
+    }
+    let name = family.strip_prefix(' ').unwrap_or(family);
+    let prefix = ["B", "I", "P"][variant as usize - 1];
+    app::font_index(&format!("{prefix}{name}"))
+        .map(Font::by_index)
+        .unwrap_or(base)
+}
+
+// A link drawn in the message: x, y, w, h in the coordinates of the outer window and the target.
+type Link = (i32, i32, i32, i32, String);
+
+// A piece of a wrapped message line, `x` pixels from the start of the line.
+struct Run {
+    span: Span,
+    x: i32,
+}
+
+// Space taken by the row of action buttons: button height and the margin around it.
+fn button_row(style: &Style) -> (i32, i32) {
+    (style.message.2 + 10, 6)
+}
+
+// FLTK only draws opaque colors, so a translucent one is blended with the background
+// it is drawn on. The alpha of the background itself is the opacity of the window.
+fn color(value: &str, background: &str) -> Color {
+    let background = Rgba::from_hex(background).unwrap_or_default();
+    let (r, g, b) = Rgba::from_hex(value)
+        .unwrap_or_default()
+        .over(background)
+        .rgb8();
+    Color::from_rgb(r, g, b)
+}
+
+fn line_height(style: &Style, size: i32) -> i32 {
+    draw::set_font(font_variant(&style.font_family, false, false), size);
+    draw::height()
+}
+
+// Breaks the spans into lines no wider than `width`, cut to `style.max_lines` (0 keeps
+// them all) with an ellipsis at the end of the last one.
+fn wrap(spans: &[Span], style: &Style, width: i32) -> Vec<Vec<Run>> {
+    let size = style.message.2;
+    let mut lines: Vec<Vec<Run>> = vec![Vec::new()];
+    let mut x = 0;
+    for span in spans {
+        draw::set_font(
+            font_variant(&style.font_family, span.bold, span.italic),
+            size,
+        );
+        let piece = |text: &str, x: i32| Run {
+            span: Span {
+                text: text.to_string(),
+                link: span.link.clone(),
+                ..*span
+            },
+            x,
+        };
+        for (i, paragraph) in span.text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Vec::new());
+                x = 0;
+            }
+            // Words keep the whitespace after them, which may hang past the edge.
+            for mut word in paragraph.split_inclusive(char::is_whitespace) {
+                if x > 0 && x + draw::width(word.trim_end()) as i32 > width {
+                    lines.push(Vec::new());
+                    x = 0;
+                }
+                if x == 0 && word.trim().is_empty() {
+                    continue;
+                }
+                // Words wider than a whole line are broken anywhere.
+                while x == 0 && !word.is_empty() && draw::width(word.trim_end()) as i32 > width {
+                    let mut end = word.len();
+                    while end > 0 && draw::width(&word[..end]) as i32 > width {
+                        end = word[..end].char_indices().last().map_or(0, |(i, _)| i);
+                    }
+                    if end == 0 {
+                        end = word.chars().next().map_or(word.len(), char::len_utf8);
+                    }
+                    lines.last_mut().unwrap().push(piece(&word[..end], 0));
+                    lines.push(Vec::new());
+                    word = &word[end..];
+                }
+                lines.last_mut().unwrap().push(piece(word, x));
+                x += draw::width(word) as i32;
+            }
+        }
+    }
+
+    if style.max_lines > 0 && lines.len() > style.max_lines {
+        lines.truncate(style.max_lines);
+        let line = lines.last_mut().unwrap();
+        // Drop characters from the end until the ellipsis fits.
+        while let Some(run) = line.last_mut() {
+            draw::set_font(
+                font_variant(&style.font_family, run.span.bold, run.span.italic),
+                size,
+            );
+            let text = format!("{}\u{2026}", run.span.text.trim_end());
+            if run.x + draw::width(&text) as i32 <= width || run.span.text.trim().is_empty() {
+                run.span.text = text;
+                break;
+            }
+            run.span.text.pop();
+            if run.span.text.trim().is_empty() {
+                line.pop();
+            }
+        }
+        if line.is_empty() {
+            line.push(Run {
+                span: Span {
+                    text: "\u{2026}".to_string(),
+                    ..Span::default()
+                },
+                x: 0,
+            });
+        }
+    }
+    lines
+}
+
+fn spans(notification: &Notification) -> Vec<Span> {
+    if notification.markup {
+        markup::parse(&notification.message)
+    } else {
+        vec![Span {
+            text: markup::strip(&notification.message),
+            ..Span::default()
+        }]
+    }
+}
+
+// Width the message wraps at, between its x offset and the right padding.
+fn wrap_width(style: &Style, layout: &Layout, notification: &Notification) -> i32 {
+    let (border, icon) = (style.border, style.icon);
+    let mut width = layout.size.0 - border.0 * 2 - border.1;
+    if notification.icon.is_some() {
+        width -= icon.0 + icon.2;
+    }
+    // A narrow window with an icon or a thick border leaves no room, but every line
+    // still takes at least one character.
+    (width - 10 - style.message.0).max(1)
+}
+
+// The configured height, or with `auto_height` whatever fits the content up to `max_height`.
+fn window_height(style: &Style, layout: &Layout, notification: &Notification, lines: usize) -> i32 {
+    let height = layout.size.1;
+    if !layout.auto_height {
+        return height;
+    }
+    let border = style.border.0 * 2 + style.border.1;
+    (content_height(style, notification, lines) + border)
+        .clamp(height, layout.max_height.max(height))
+}
+
+// Height of the inner window that fits the title, `lines` lines of message and the
+// icon, buttons and bar below them.
+fn content_height(style: &Style, notification: &Notification, lines: usize) -> i32 {
+    let line_h = line_height(style, style.message.2);
+    let text_bottom = 20 + style.message.1 + line_h * (lines as i32 - 1);
+    let mut height = (20 + style.title.1).max(text_bottom) + 10;
+    if notification.icon.is_some() {
+        height = height.max(style.icon.2 + style.icon.1.abs() + 10);
+    }
+    if notification.actions.iter().any(|a| a.key != "default") {
+        let (row_h, margin) = button_row(style);
+        height += row_h + margin;
+    }
+    if notification.progress.is_some() && style.progress.1 < 0 {
+        height += style.progress.2 - style.progress.1;
+    }
+    height
+}
+
+// Draws the wrapped message where FLTK put its label before (the first line just above
+// the frame) and records where the links ended up.
+fn draw_lines(frame: &Frame, lines: &[Vec<Run>], style: &Style, offset: (i32, i32)) -> Vec<Link> {
+    let mut links = Vec::new();
+    let size = style.message.2;
+    let line_h = line_height(style, size);
+    draw::set_draw_color(color(&style.colors.3, &style.colors.0));
+    for (i, line) in lines.iter().enumerate() {
+        let y = frame.y() - line_h + line_h * i as i32;
+        let baseline = y + line_h - draw::descent();
+        for run in line {
+            let span = &run.span;
+            draw::set_font(
+                font_variant(&style.font_family, span.bold, span.italic),
+                size,
+            );
+            let x = frame.x() + run.x;
+            draw::draw_text(&span.text, x, baseline);
+            let w = draw::width(span.text.trim_end()) as i32;
+            if span.underline || span.link.is_some() {
+                draw::draw_line(x, baseline + 1, x + w, baseline + 1);
+            }
+            if let Some(href) = &span.link {
+                links.push((x + offset.0, y + offset.1, w, line_h, href.clone()));
+            }
+        }
+    }
+    links
+}
+
+fn notify_closed(waiter: Option<Sender<Reply>>, id: u32, reason: CloseReason) {
+    if let Some(waiter) = waiter {
+        let _ = waiter.send(Reply::Closed { id, reason });
+    }
+}
+
+// Where the stack starts: x, the top edge for the top placements or the bottom edge for
+// the bottom ones, and the direction the next popups grow in.
+fn anchor(layout: &Layout, placement: &str) -> (i32, i32, i32) {
+    let (sx, sy, sw, sh) = screen_xywh(layout.monitor);
+    let (ax, ay) = layout.offset;
+    let aw = layout.size.0;
+    match placement {
+        "top_left" => (sx + ax, sy + ay, 1),
+        "top_center" => (sx + (sw - aw) / 2, sy + ay, 1),
+        "top_right" => (sx + sw - aw - ax, sy + ay, 1),
+        "bottom_left" => (sx + ax, sy + sh - ay, -1),
+        "bottom_center" => (sx + (sw - aw) / 2, sy + sh - ay, -1),
+        "bottom_right" => (sx + sw - aw - ax, sy + sh - ay, -1),
+        _ => (20, 30, 1),
+    }
+}
+
+impl Popup {
+    fn new(queued: Queued, styles: &Styles, layout: &Layout, clicked: app::Sender<Click>) -> Popup {
+        let Queued {
+            id,
+            notification,
+            sent,
+            outcome,
+            waiter,
+        } = queued;
+        let mut style = styles.get(notification.urgency).clone();
+        for over in &outcome.styles {
+            over.apply(&mut style);
+        }
+        let style = &style;
+        let wrap_width = wrap_width(style, layout, &notification);
+        let lines = wrap(&spans(&notification), style, wrap_width);
+        let width = layout.size.0;
+        let height = window_height(style, layout, &notification, lines.len());
+        let (border, title, message, icon) = (style.border, style.title, style.message, style.icon);
+        let colors = style.colors.clone();
+
+        let mut wind1 = Window::new(0, 0, width, height, "Pino");
+        wind1.set_color(color(&colors.1, &colors.0));
+
+        let mut wind2 = Window::new(
+            border.0 + border.1 / 2,
+            border.0 + border.1 / 2,
+            width - border.0 * 2 - border.1,
+            height - border.0 * 2 - border.1,
+            "Pino",
+        );
+        wind2.set_color(color(&colors.0, &colors.0));
+
+        // The icon takes a column on one side and the text moves out of its way.
+        let (icon_frame, text_x, text_w) = match notification.icon {
+            Some(_) => {
+                let column = icon.0 + icon.2;
+                let left = style.icon_position != "right";
+                let x = if left { icon.0 } else { wind2.w() - column };
+                let frame = Frame::new(x, (wind2.h() - icon.2) / 2 + icon.1, icon.2, icon.2, "");
+                (
+                    Some(frame),
+                    if left { column } else { 0 },
+                    wind2.w() - column,
+                )
+            }
+            None => (None, 0, wind2.w()),
+        };
+
+        let mut title_frame = Frame::new(
+            5 + title.0 + text_x,
+            20 + title.1,
+            text_w - 10,
+            wind2.h() - 10,
+            "",
+        );
+        let mut message_frame = Frame::new(
+            5 + message.0 + text_x,
+            20 + message.1,
+            text_w - 10,
+            wind2.h() - 10,
+            "",
+        );
 
-    wind1.handle(|_, event| {
-        if event == Event::Push {
-            app::quit();
-            true
+        // Action buttons share a row along the bottom edge.
+        let buttons: Vec<&Action> = notification
+            .actions
+            .iter()
+            .filter(|action| action.key != "default")
+            .collect();
+        let (row_h, margin) = button_row(style);
+        let bottom = if buttons.is_empty() {
+            wind2.h()
         } else {
-            false
+            wind2.h() - row_h - margin
+        };
+        let button_w = (wind2.w() - margin) / buttons.len().max(1) as i32 - margin;
+        for (i, action) in buttons.iter().enumerate() {
+            let mut button = Button::new(
+                margin + (button_w + margin) * i as i32,
+                bottom,
+                button_w,
+                row_h,
+                None,
+            );
+            button.set_label(&action.label);
+            button.set_frame(FrameType::RFlatBox);
+            button.set_color(color(&colors.1, &colors.0));
+            button.set_label_color(color(&colors.0, &colors.0));
+            button.set_label_font(Font::by_name(style.font_family.as_str()));
+            button.set_label_size(message.2);
+            button.clear_visible_focus();
+            let key = action.key.clone();
+            button.set_callback(move |_| clicked.send((id, Some(key.clone()))));
         }
-    });
+        // Same row in the coordinates of the outer window, where clicks arrive first.
+        let row = (wind2.x(), wind2.y() + bottom, wind2.w(), row_h);
 
-    let (tx, rx) = mpsc::channel::<(String, String, u64)>();
-    tx.send((data.0, data.1, data.2)).unwrap();
-
-    if Path::new(&socket_path).exists() {
-        std::fs::remove_file(&socket_path).unwrap();
-    }
-
-    let socket = socket_path.clone();
-    thread::spawn(move || {
-        let listener = UnixListener::bind(&socket).unwrap();
-
-        for stream in listener.incoming() {
-            match stream {
-                Ok(mut stream) => {
-                    let mut buffer = [0; 1024];
-                    let n = stream.read(&mut buffer).unwrap();
-                    let received = String::from_utf8_lossy(&buffer[..n]).to_string();
-
-                    let parts: Vec<String> = received
-                        .split("|+|")
-                        .map(|item| item.replace('\n', ""))
-                        .collect();
-                    if parts.len() >= 3 {
-                        let title = parts[0].to_string();
-                        let message = parts[1].to_string();
-                        let delay = parts[2].parse::<u64>().unwrap_or_else(|_| {
-                            eprintln!("Invalid delay value, using 3s");
-                            3
-                        });
-                        tx.send((title, message, delay)).unwrap();
-                    }
+        let progress = notification.progress.map(|_| {
+            let (x, y, bar_h, radius) = style.progress;
+            let y = if y < 0 { bottom - bar_h + y } else { y };
+            let mut bar = Frame::new(x, y, wind2.w() - x * 2, bar_h, "");
+            let value = Rc::new(Cell::new(0u8));
+            let shown = Rc::clone(&value);
+            let fill = color(&style.progress_colors.0, &colors.0);
+            let trough = color(&style.progress_colors.1, &colors.0);
+            bar.draw(move |f| {
+                draw::set_draw_color(trough);
+                draw::draw_rounded_rectf(f.x(), f.y(), f.w(), f.h(), radius);
+                let filled = f.w() * shown.get().min(100) as i32 / 100;
+                if filled > 0 {
+                    draw::set_draw_color(fill);
+                    let w = filled.max(radius * 2).min(f.w());
+                    draw::draw_rounded_rectf(f.x(), f.y(), w, f.h(), radius);
                 }
-                Err(e) => {
-                    eprintln!("Error accepting connection: {}", e);
-                    break;
+            });
+            value
+        });
+
+        title_frame.set_align(Align::Top | Align::Left);
+        title_frame.set_label_color(color(&colors.2, &colors.0));
+        title_frame.set_label_font(Font::by_name(style.font_family.as_str()));
+        title_frame.set_label_size(title.2);
+
+        let lines = Rc::new(RefCell::new(lines));
+        let links = Rc::new(RefCell::new(Vec::new()));
+        let (wrapped, drawn) = (Rc::clone(&lines), Rc::clone(&links));
+        let (text_style, offset) = (style.clone(), (wind2.x(), wind2.y()));
+        message_frame.draw(move |f| {
+            *drawn.borrow_mut() = draw_lines(f, &wrapped.borrow(), &text_style, offset);
+        });
+
+        wind2.end();
+        wind1.end();
+
+        wind1.draw(move |f| {
+            draw::set_draw_color(color(&colors.0, &colors.0));
+            draw::draw_rounded_rectf(
+                border.0 - 1,
+                border.0 - 1,
+                f.w() - border.0 * 2 + 2,
+                f.h() - border.0 * 2 + 2,
+                border.1,
+            );
+        });
+
+        let has_buttons = !buttons.is_empty();
+        wind1.handle(move |_, event| {
+            let (x, y) = app::event_coords();
+            let on_buttons = has_buttons
+                && (row.0..row.0 + row.2).contains(&x)
+                && (row.1..row.1 + row.3).contains(&y);
+            let link = links.borrow().iter().find_map(|(lx, ly, lw, lh, href)| {
+                ((*lx..lx + lw).contains(&x) && (*ly..ly + lh).contains(&y)).then(|| href.clone())
+            });
+            if event == Event::Push
+                && let Some(href) = link
+            {
+                if let Err(e) = process::Command::new("xdg-open").arg(&href).spawn() {
+                    eprintln!("Can't open {href}: {e}");
                 }
+                true
+            } else if event == Event::Push && !on_buttons {
+                clicked.send((id, None));
+                true
+            } else {
+                false
             }
+        });
+
+        wind1.set_override();
+
+        let mut popup = Popup {
+            id,
+            notification: notification.clone(),
+            sent,
+            window: wind1,
+            title: title_frame,
+            message: message_frame,
+            lines,
+            wrap_width,
+            icon: icon_frame,
+            progress,
+            style: style.clone(),
+            outcome,
+            expires: None,
+            waiter,
+        };
+        popup.update(&notification);
+        popup
+    }
+
+    // Shows new content in place and restarts the timer.
+    fn update(&mut self, notification: &Notification) {
+        if self.title.label() != notification.title {
+            self.title.set_label(&notification.title);
         }
-    });
+        *self.lines.borrow_mut() = wrap(&spans(notification), &self.style, self.wrap_width);
+        self.message.redraw();
+        if let (Some(frame), Some(icon)) = (&mut self.icon, &notification.icon) {
+            let size = self.style.icon.2;
+            if !icon::show(frame, icon, &self.style.icon_theme, size) {
+                eprintln!("Can't load the icon of notification {}", self.id);
+            }
+        }
+        if let (Some(value), Some(progress)) = (&self.progress, notification.progress) {
+            value.set(progress);
+        }
+        self.window.redraw();
+        self.expires = match notification.delay.unwrap_or(self.style.delay) {
+            0 => None,
+            delay => Some(Instant::now() + Duration::from_secs(delay)),
+        };
+        self.notification = notification.clone();
+    }
 
-    wind1.set_override();
-    wind1.show();
-    wind2.show();
+    // Whether `update` can show the notification, or the layout has to be built again.
+    fn fits(&self, notification: &Notification, outcome: &Outcome, layout: &Layout) -> bool {
+        self.notification.urgency == notification.urgency
+            && self.outcome == *outcome
+            && self.icon.is_some() == notification.icon.is_some()
+            && self.progress.is_some() == notification.progress.is_some()
+            && self.notification.actions == notification.actions
+            && (!layout.auto_height || {
+                let lines = wrap(&spans(notification), &self.style, self.wrap_width).len();
+                window_height(&self.style, layout, notification, lines) == self.window.h()
+            })
+    }
+
+    fn close(mut self, reason: CloseReason) {
+        notify_closed(self.waiter.take(), self.id, reason);
+        self.destroy();
+    }
 
-    let mut close_timer: Option<Instant> = None;
-    let mut current_delay_secs: Option<u64> = None;
+    fn destroy(self) {
+        let mut window = self.window;
+        window.hide();
+        Window::delete(window);
+    }
+}
 
-    while app.wait() {
-        if let Ok((title, message, delay)) = rx.try_recv() {
-            if title_frame.label() != title {
-                title_frame.set_label(&title);
+// One notification listing what arrived during do-not-disturb.
+fn summary(held: &[Queued]) -> Notification {
+    const LISTED: usize = 5;
+    let mut message: Vec<String> = held
+        .iter()
+        .take(LISTED)
+        .map(|queued| format!("\u{2022} {}", queued.notification.title))
+        .collect();
+    if held.len() > LISTED {
+        message.push(format!(
+            "and {} more, see pino history",
+            held.len() - LISTED
+        ));
+    }
+    let urgency = held
+        .iter()
+        .map(|queued| queued.notification.urgency)
+        .max_by_key(|urgency| *urgency as u8)
+        .unwrap_or_default();
+    Notification {
+        title: format!(
+            "{} while Do Not Disturb was on",
+            match held.len() {
+                1 => "1 notification".to_string(),
+                n => format!("{n} notifications"),
             }
-            if message_frame.label() != message {
-                message_frame.set_label(&message);
+        ),
+        message: message.join("\n"),
+        markup: false,
+        delay: None,
+        urgency,
+        icon: None,
+        progress: None,
+        actions: vec![],
+        app_name: None,
+        sound: None,
+    }
+}
+
+struct Stack {
+    styles: Styles,
+    layout: Layout,
+    rules: Rules,
+    player: Player,
+    popups: Vec<Popup>,
+    pending: VecDeque<Queued>,
+    next_id: u32,
+    clicked: app::Sender<Click>,
+    changed: bool,
+    history: History,
+    dnd: Dnd,
+    // notifications that arrived during do-not-disturb
+    held: Vec<Queued>,
+    // notifications the rules suppressed, closed once their ID went out
+    suppressed: Vec<(u32, Option<Sender<Reply>>)>,
+    reload_visible: bool,
+    // keeps running when there is nothing left to show
+    daemon: bool,
+}
+
+impl Stack {
+    fn new(settings: Settings, clicked: app::Sender<Click>, daemon: bool) -> Stack {
+        let Settings {
+            styles,
+            layout,
+            rules,
+            history,
+            dnd,
+            sound,
+            reload_visible,
+        } = settings;
+        Stack {
+            styles,
+            layout,
+            rules,
+            player: Player::new(sound),
+            popups: Vec::new(),
+            pending: VecDeque::new(),
+            next_id: 1,
+            clicked,
+            changed: false,
+            history,
+            dnd,
+            held: Vec::new(),
+            suppressed: Vec::new(),
+            reload_visible,
+            daemon,
+        }
+    }
+
+    // Takes over a config that was read again. What shows up from now on follows it,
+    // and with `reload_visible` so do the popups on screen, which keep their time left.
+    fn reload(&mut self, settings: Settings) {
+        let Settings {
+            styles,
+            layout,
+            rules,
+            history,
+            dnd,
+            sound,
+            reload_visible,
+        } = settings;
+        self.styles = styles;
+        self.layout = layout;
+        self.rules = rules;
+        self.history = history;
+        // Whether do-not-disturb is on belongs to the running server, not the config.
+        self.dnd.bypass_critical = dnd.bypass_critical;
+        self.dnd.on_end = dnd.on_end;
+        self.player = Player::new(sound);
+        self.reload_visible = reload_visible;
+
+        if self.reload_visible {
+            for popup in self.popups.iter_mut() {
+                let queued = Queued {
+                    id: popup.id,
+                    notification: popup.notification.clone(),
+                    sent: popup.sent.clone(),
+                    outcome: popup.outcome.clone(),
+                    waiter: popup.waiter.take(),
+                };
+                let mut rebuilt = Popup::new(queued, &self.styles, &self.layout, self.clicked);
+                rebuilt.expires = popup.expires;
+                std::mem::replace(popup, rebuilt).destroy();
             }
-            wind2.redraw();
+        }
+        self.changed = true;
+    }
 
-            if current_delay_secs.map(|d| d != delay).unwrap_or(true) {
-                current_delay_secs = Some(delay);
-                close_timer = Some(Instant::now() + Duration::from_secs(delay));
+    // Runs the rules over a notification and queues it, or updates the one with the
+    // `replaces` ID in place if it is still around, and returns the ID it is known by.
+    fn notify(
+        &mut self,
+        mut notification: Notification,
+        replaces: Option<u32>,
+        waiter: Option<Sender<Reply>>,
+    ) -> u32 {
+        let sent = notification.clone();
+        let outcome = self.rules.apply(&mut notification);
+        if outcome.suppress {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.suppressed.push((id, waiter));
+            return id;
+        }
+
+        if let Some(id) = replaces {
+            if let Some(i) = self.popups.iter().position(|p| p.id == id) {
+                let popup = &mut self.popups[i];
+                let previous = std::mem::replace(&mut popup.waiter, waiter);
+                notify_closed(previous, id, CloseReason::Replaced);
+
+                if popup.fits(&notification, &outcome, &self.layout) {
+                    popup.update(&notification);
+                    popup.sent = sent;
+                } else {
+                    // A different urgency, rule, icon, bar, buttons or height changes the layout, so build it again.
+                    let queued = Queued {
+                        id,
+                        notification,
+                        sent,
+                        outcome,
+                        waiter: popup.waiter.take(),
+                    };
+                    let popup = Popup::new(queued, &self.styles, &self.layout, self.clicked);
+                    std::mem::replace(&mut self.popups[i], popup).destroy();
+                    self.changed = true;
+                }
+                return id;
             }
+            let mut waiting = self.pending.iter_mut().chain(self.held.iter_mut());
+            if let Some(queued) = waiting.find(|q| q.id == id) {
+                queued.notification = notification;
+                queued.sent = sent;
+                queued.outcome = outcome;
+                notify_closed(
+                    std::mem::replace(&mut queued.waiter, waiter),
+                    id,
+                    CloseReason::Replaced,
+                );
+                return id;
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let bypass = self.dnd.bypass_critical && notification.urgency == Urgency::Critical;
+        let queued = Queued {
+            id,
+            notification,
+            sent,
+            outcome,
+            waiter,
+        };
+        if self.dnd.enabled && !bypass && !self.daemon {
+            // Without a daemon the server quits once it has nothing to show, so holding
+            // the notification back would keep the sender waiting until do-not-disturb
+            // ends. It goes to the history and closes right after its ID went out.
+            self.history.record(&queued.sent, CloseReason::Closed);
+            self.suppressed.push((id, queued.waiter));
+        } else if self.dnd.enabled && !bypass {
+            self.held.push(queued);
+        } else {
+            self.pending.push_back(queued);
+            self.changed = true;
+        }
+        id
+    }
+
+    // Switches do-not-disturb and returns its state. Ending it releases what was held
+    // back as one summary notification, or drops it if `on_end` is "discard".
+    fn set_dnd(&mut self, mode: DndMode) -> Reply {
+        let enabled = match mode {
+            DndMode::On => true,
+            DndMode::Off => false,
+            DndMode::Toggle => !self.dnd.enabled,
+            DndMode::Status => self.dnd.enabled,
+        };
+        if enabled != self.dnd.enabled {
+            self.dnd.enabled = enabled;
+            dnd::save(self.dnd.session, enabled);
+        }
+        if !enabled {
+            self.release_held();
+        }
+        Reply::Dnd {
+            enabled,
+            held: self.held.len(),
+        }
+    }
+
+    // Closes what do-not-disturb held back, after queueing their summary unless `on_end`
+    // is "discard".
+    fn release_held(&mut self) {
+        if self.held.is_empty() {
+            return;
+        }
+        let held: Vec<Queued> = self.held.drain(..).collect();
+        if self.dnd.on_end != "discard" {
+            self.notify(summary(&held), None, None);
+        }
+        for queued in held {
+            self.history.record(&queued.sent, CloseReason::Closed);
+            notify_closed(queued.waiter, queued.id, CloseReason::Closed);
+        }
+    }
+
+    fn close(&mut self, id: u32, reason: CloseReason) -> bool {
+        if let Some(i) = self.popups.iter().position(|p| p.id == id) {
+            let popup = self.popups.remove(i);
+            self.history.record(&popup.sent, reason);
+            popup.close(reason);
+        } else if let Some(i) = self.pending.iter().position(|q| q.id == id) {
+            let queued = self.pending.remove(i).unwrap();
+            self.history.record(&queued.sent, reason);
+            notify_closed(queued.waiter, id, reason);
+        } else if let Some(i) = self.held.iter().position(|q| q.id == id) {
+            let queued = self.held.remove(i);
+            self.history.record(&queued.sent, reason);
+            notify_closed(queued.waiter, id, reason);
+        } else {
+            return false;
+        }
+        self.changed = true;
+        true
+    }
+
+    // Reports the action picked on a popup (clicking the popup itself picks its
+    // "default" action if it has one) and dismisses it.
+    fn click(&mut self, id: u32, key: Option<String>) {
+        let Some(popup) = self.popups.iter().find(|p| p.id == id) else {
+            return;
+        };
+        let key = key.or_else(|| {
+            popup
+                .notification
+                .actions
+                .iter()
+                .any(|action| action.key == "default")
+                .then(|| "default".to_string())
+        });
+        if let (Some(waiter), Some(key)) = (&popup.waiter, key) {
+            let _ = waiter.send(Reply::Action { id, key });
+        }
+        self.close(id, CloseReason::Dismissed);
+    }
+
+    fn close_all(&mut self, reason: CloseReason) {
+        for popup in self.popups.drain(..) {
+            self.history.record(&popup.sent, reason);
+            popup.close(reason);
         }
+        for queued in self.pending.drain(..).chain(self.held.drain(..)) {
+            self.history.record(&queued.sent, reason);
+            notify_closed(queued.waiter, queued.id, reason);
+        }
+        for (id, waiter) in self.suppressed.drain(..) {
+            notify_closed(waiter, id, reason);
+        }
+        self.changed = true;
+    }
+
+    // Closes what the rules suppressed and the popups whose time is up.
+    fn expire(&mut self) {
+        for (id, waiter) in self.suppressed.drain(..) {
+            notify_closed(waiter, id, CloseReason::Closed);
+        }
+        let now = Instant::now();
+        while let Some(i) = self
+            .popups
+            .iter()
+            .position(|p| p.expires.is_some_and(|expires| expires <= now))
+        {
+            let popup = self.popups.remove(i);
+            self.history.record(&popup.sent, CloseReason::Expired);
+            popup.close(CloseReason::Expired);
+            self.changed = true;
+        }
+    }
 
-        if let Some(timer) = close_timer {
-            if Instant::now() >= timer {
-                app::quit();
+    // Moves queued notifications on screen while there is room and lays the stack out again.
+    fn refresh(&mut self) {
+        if !self.changed {
+            return;
+        }
+        self.changed = false;
+
+        while self.popups.len() < self.layout.max_visible.max(1) {
+            let Some(queued) = self.pending.pop_front() else {
                 break;
+            };
+            let popup = Popup::new(queued, &self.styles, &self.layout, self.clicked);
+            let sound = popup
+                .notification
+                .sound
+                .as_ref()
+                .unwrap_or(&popup.style.sound);
+            self.player.play(sound);
+            self.popups.push(popup);
+        }
+
+        // Popups may differ in height, so each one starts where the previous one with
+        // the same placement ended.
+        let mut edges: HashMap<String, (i32, i32, i32)> = HashMap::new();
+        for popup in self.popups.iter_mut() {
+            let placement = popup
+                .outcome
+                .placement
+                .clone()
+                .unwrap_or_else(|| self.layout.placement.clone());
+            let (x, edge, direction) = edges
+                .entry(placement)
+                .or_insert_with_key(|placement| anchor(&self.layout, placement));
+            let h = popup.window.h();
+            let y = if *direction > 0 { *edge } else { *edge - h };
+            *edge += (h + self.layout.gap) * *direction;
+            popup.window.set_pos(*x, y);
+            if !popup.window.shown() {
+                popup.window.show();
+                let opacity = Rgba::from_hex(&popup.style.colors.0).map_or(1.0, |c| c.a);
+                if opacity < 1.0 {
+                    popup.window.set_opacity(opacity);
+                }
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.popups.is_empty() && self.pending.is_empty() && self.held.is_empty()
+    }
+}
+
+// How long the config files have to stay untouched before they are read again, so
+// an editor saving in several steps causes one reload.
+const SETTLE: Duration = Duration::from_millis(200);
+
+// Runs the notification server on `socket_path`. A daemon keeps the app and its
+// fonts loaded until it receives SIGINT/SIGTERM or a quit request, otherwise the
+// server exits as soon as the last notification closes.
+//
+// `load` reads the config into settings and names the files to watch for changes.
+// It runs again on SIGHUP, a reload request or once one of those files changed.
+pub fn ui(
+    load: impl Fn() -> (Settings, Vec<PathBuf>),
+    first: Option<Command>,
+    socket_path: String,
+    daemon: bool,
+) {
+    let _app = app::App::default().load_system_fonts();
+
+    let terminate = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&terminate))
+            .expect("Can't register signal handler");
+    }
+    let reload = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, Arc::clone(&reload))
+        .expect("Can't register signal handler");
+    let mut watcher = Watcher::new(Arc::clone(&reload))
+        .map_err(|e| eprintln!("Can't watch the config files: {e}"))
+        .ok();
+    let mut read_config = || {
+        let (settings, files) = load();
+        if let Some(watcher) = &mut watcher {
+            watcher.watch(&files);
+        }
+        settings
+    };
+
+    let (tx, rx) = mpsc::channel::<Command>();
+    if let Some(command) = first {
+        tx.send(command).unwrap();
+    }
+    #[cfg(feature = "dbus")]
+    if daemon {
+        crate::dbus::serve(tx.clone());
+    }
+    server::listen(&socket_path, tx);
+
+    let (clicked, clicks) = app::channel::<Click>();
+    let mut stack = Stack::new(read_config(), clicked, daemon);
+    let mut reload_at = None;
+
+    'events: loop {
+        // Errors here only mean the wait was interrupted by a signal.
+        let _ = app::wait_for(0.1);
+        if terminate.load(Ordering::Relaxed) {
+            break;
+        }
+        if reload.swap(false, Ordering::Relaxed) {
+            reload_at = Some(Instant::now() + SETTLE);
+        }
+        if reload_at.is_some_and(|at| at <= Instant::now()) {
+            reload_at = None;
+            stack.reload(read_config());
+        }
+
+        for command in rx.try_iter() {
+            match command {
+                Command::Notify {
+                    notification,
+                    replaces,
+                    wait,
+                    reply,
+                } => {
+                    let waiter = wait.then(|| reply.clone());
+                    let id = stack.notify(notification, replaces, waiter);
+                    let _ = reply.send(Reply::Shown { id });
+                }
+                Command::Close { id, reply } => {
+                    let _ = reply.send(if stack.close(id, CloseReason::Closed) {
+                        Reply::Ok
+                    } else {
+                        Reply::Error {
+                            message: format!("no notification with ID {id}"),
+                        }
+                    });
+                }
+                Command::CloseAll { reply } => {
+                    stack.close_all(CloseReason::Closed);
+                    let _ = reply.send(Reply::Ok);
+                }
+                Command::Dnd { mode, reply } => {
+                    let _ = reply.send(stack.set_dnd(mode));
+                }
+                Command::Reload { reply } => {
+                    stack.reload(read_config());
+                    let _ = reply.send(Reply::Ok);
+                }
+                Command::Quit { reply } => {
+                    let _ = reply.send(Reply::Ok);
+                    break 'events;
+                }
             }
         }
+
+        while let Some((id, key)) = clicks.recv() {
+            stack.click(id, key);
+        }
+        stack.expire();
+        stack.refresh();
+
+        if !daemon && stack.is_empty() {
+            break;
+        }
+    }
+
+    stack.close_all(CloseReason::Closed);
+    server::cleanup(&socket_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(on_end: &str) -> Settings {
+        let style = Style {
+            font_family: String::new(),
+            border: (0, 0),
+            title: (0, 0, 12),
+            message: (0, 0, 12),
+            max_lines: 0,
+            colors: Default::default(),
+            delay: 5,
+            icon: (0, 0, 48),
+            icon_position: "left".to_string(),
+            icon_theme: "hicolor".to_string(),
+            progress: (0, 0, 6, 3),
+            progress_colors: Default::default(),
+            sound: String::new(),
+        };
+        Settings {
+            styles: Styles {
+                base: style.clone(),
+                low: style.clone(),
+                normal: style.clone(),
+                critical: style,
+            },
+            layout: Layout {
+                monitor: 0,
+                placement: "top_center".to_string(),
+                offset: (0, 0),
+                size: (400, 60),
+                auto_height: false,
+                max_height: 300,
+                gap: 10,
+                max_visible: 5,
+            },
+            rules: Rules::new(0, Vec::new()),
+            // 0 keeps the tests off the real history file
+            history: History::new(0, 0),
+            dnd: Dnd {
+                session: 0,
+                enabled: true,
+                bypass_critical: true,
+                on_end: on_end.to_string(),
+            },
+            sound: false,
+            reload_visible: false,
+        }
+    }
+
+    fn notification(title: &str, urgency: Urgency) -> Notification {
+        Notification {
+            urgency,
+            title: title.to_string(),
+            ..summary(&[])
+        }
+    }
+
+    // Holds back two notifications, with their senders waiting, and ends do-not-disturb.
+    fn release(on_end: &str) -> (Stack, Vec<Reply>) {
+        let (clicked, _) = app::channel();
+        let mut stack = Stack::new(settings(on_end), clicked, true);
+        let (waiter, replies) = mpsc::channel();
+        for title in ["one", "two"] {
+            stack.notify(
+                notification(title, Urgency::Low),
+                None,
+                Some(waiter.clone()),
+            );
+        }
+        assert_eq!(stack.held.len(), 2);
+        assert!(stack.pending.is_empty());
+
+        // Status leaves the saved state alone, and finds do-not-disturb off.
+        stack.dnd.enabled = false;
+        let reply = stack.set_dnd(DndMode::Status);
+        assert!(matches!(
+            reply,
+            Reply::Dnd {
+                enabled: false,
+                held: 0
+            }
+        ));
+        (stack, replies.try_iter().collect())
+    }
+
+    fn closed(replies: &[Reply]) -> Vec<u32> {
+        replies
+            .iter()
+            .map(|reply| match reply {
+                Reply::Closed {
+                    id,
+                    reason: CloseReason::Closed,
+                } => *id,
+                _ => panic!("not closed: {reply:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ending_dnd_shows_a_summary() {
+        let (stack, replies) = release("summary");
+        assert_eq!(closed(&replies), vec![1, 2]);
+        assert!(stack.held.is_empty());
+        let summary: Vec<_> = stack.pending.iter().map(|q| &q.notification).collect();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(
+            summary[0].title,
+            "2 notifications while Do Not Disturb was on"
+        );
+        assert_eq!(summary[0].message, "\u{2022} one\n\u{2022} two");
+    }
+
+    #[test]
+    fn ending_dnd_can_discard() {
+        let (stack, replies) = release("discard");
+        assert_eq!(closed(&replies), vec![1, 2]);
+        assert!(stack.held.is_empty() && stack.pending.is_empty());
     }
-    if Path::new(&socket_path).exists() {
-        std::fs::remove_file(socket_path).unwrap();
+
+    #[test]
+    fn critical_bypasses_dnd() {
+        let (clicked, _) = app::channel();
+        let mut stack = Stack::new(settings("summary"), clicked, true);
+        stack.notify(notification("fire", Urgency::Critical), None, None);
+        assert!(stack.held.is_empty());
+        assert_eq!(stack.pending.len(), 1);
+    }
+
+    #[test]
+    fn only_a_daemon_holds_back() {
+        let (clicked, _) = app::channel();
+        let mut stack = Stack::new(settings("summary"), clicked, false);
+        let (waiter, replies) = mpsc::channel();
+        let id = stack.notify(notification("one", Urgency::Low), None, Some(waiter));
+        assert!(stack.is_empty());
+
+        stack.expire();
+        assert_eq!(closed(&replies.try_iter().collect::<Vec<_>>()), vec![id]);
     }
 }
@@ -0,0 +1,88 @@
+use fltk::app;
+use inotify::{Inotify, WatchMask, Watches};
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+};
+
+// Raises `changed` whenever one of the watched files is written, replaced or removed.
+// Their directories are watched instead of the files, since editors often save by
+// writing a new file and moving it over the old one.
+pub struct Watcher {
+    watches: Watches,
+    dirs: HashSet<PathBuf>,
+    names: Arc<Mutex<HashSet<OsString>>>,
+}
+
+impl Watcher {
+    pub fn new(changed: Arc<AtomicBool>) -> io::Result<Watcher> {
+        let mut inotify = Inotify::init()?;
+        let watches = inotify.watches();
+        let names = Arc::new(Mutex::new(HashSet::new()));
+        let watched = Arc::clone(&names);
+        thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(events) = inotify.read_events_blocking(&mut buffer) {
+                let names = watched.lock().unwrap();
+                let hit = events
+                    .filter_map(|event| event.name)
+                    .any(|name| names.contains(name));
+                if hit {
+                    changed.store(true, Ordering::Relaxed);
+                    app::awake();
+                }
+            }
+        });
+        Ok(Watcher {
+            watches,
+            dirs: HashSet::new(),
+            names,
+        })
+    }
+
+    // Watches `files` from now on, besides what was watched before. Files that don't
+    // exist yet are noticed once they are created. So is a missing directory on their
+    // way, after which the reload watches the directory itself.
+    pub fn watch(&mut self, files: &[PathBuf]) {
+        for file in files {
+            let mut path = file.as_path();
+            while let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+                self.names.lock().unwrap().insert(name.to_os_string());
+                let dir = if dir == Path::new("") {
+                    Path::new(".")
+                } else {
+                    dir
+                };
+                if dir.is_dir() {
+                    self.add(dir);
+                    break;
+                }
+                path = dir;
+            }
+        }
+    }
+
+    fn add(&mut self, dir: &Path) {
+        if self.dirs.contains(dir) {
+            return;
+        }
+        let mask = WatchMask::CREATE
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::MOVED_TO
+            | WatchMask::MOVED_FROM
+            | WatchMask::DELETE;
+        match self.watches.add(dir, mask) {
+            Ok(_) => {
+                self.dirs.insert(dir.to_path_buf());
+            }
+            Err(e) => eprintln!("Can't watch {}: {e}", dir.display()),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+// $XDG_DATA_HOME and then $XDG_DATA_DIRS, where icon and sound themes live.
+pub fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(home) = env::var("HOME") {
+        let data_home = env::var("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(home).join(".local").join("share"));
+        dirs.push(data_home);
+    }
+    let data_dirs = env::var("XDG_DATA_DIRS").unwrap_or("/usr/local/share:/usr/share".to_string());
+    dirs.extend(
+        data_dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from),
+    );
+    dirs
+}
+
+// The path of a file path or file:// URI, or None for a name to look up in a theme.
+pub fn file(name: &str) -> Option<&Path> {
+    let name = name.strip_prefix("file://").unwrap_or(name);
+    name.contains('/').then(|| Path::new(name))
+}